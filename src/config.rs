@@ -0,0 +1,694 @@
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use serde::de::value::{Error as ValueError, StrDeserializer};
+use std::collections::HashMap;
+use std::env;
+use std::num::NonZeroU16;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tracing::{error, info};
+use x11rb::protocol::render::Color;
+use x11rb::protocol::xproto::ModMask;
+
+use crate::hooks::HookConfig;
+use crate::hotkeys::HotkeyConfig;
+use crate::layout::{Anchor, LayoutConfig};
+
+#[derive(Debug)]
+pub struct Config {
+    pub thumbnail: ThumbnailConfig,
+    pub hide_when_no_focus: bool,
+    /// RandR output (e.g. `DP-2`) that layouts and new thumbnails are placed on.
+    pub output: Option<String>,
+    pub snap_threshold: u16,
+    pub snap_gap: u16,
+    pub snap_bypass_modifier: Modifier,
+    /// Hold to resize thumbnails by dragging a corner or scrolling.
+    pub resize_modifier: Modifier,
+    /// Width of the enlarged preview shown when hovering a thumbnail, 0 if it is disabled.
+    pub zoom_width: u16,
+    /// How long the pointer has to rest on a thumbnail before the preview is shown.
+    pub zoom_delay: Duration,
+    /// Corner of the thumbnail the preview is aligned with.
+    pub zoom_anchor: Anchor,
+    /// Frame rate limit of the focused client's thumbnail, 0 for unlimited.
+    pub fps_focused: u32,
+    /// Frame rate limit of every other thumbnail, 0 for unlimited.
+    pub fps_background: u32,
+    pub layout: LayoutConfig,
+    pub hotkeys: HotkeyConfig,
+    pub hooks: HookConfig,
+    pub characters: HashMap<String, CharacterConfig>,
+}
+
+/// Settings of a single thumbnail, after per-character overrides have been applied.
+#[derive(Debug, Clone)]
+pub struct ThumbnailConfig {
+    pub width: u16,
+    pub height: u16,
+    pub opacity: u32,
+    pub border_size: u16,
+    pub border_color: Color,
+    pub text_x: i16,
+    pub text_y: i16,
+    pub text_foreground: u32,
+    pub text_background: u32,
+    pub capture: CaptureMode,
+    pub fit: FitMode,
+    /// Fills the bars around the client in `fit` mode.
+    pub letterbox_color: Color,
+    pub filter: Filter,
+    /// Scale large clients down in two steps through an intermediate picture.
+    pub two_pass_downscale: bool,
+    /// Parts of the client shown in windows of their own next to the thumbnail.
+    pub regions: Vec<RegionConfig>,
+}
+
+/// How thumbnails read the contents of the EVE clients.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum CaptureMode {
+    /// Render straight from the client window. Obscured parts need a compositing manager.
+    #[default]
+    Window,
+    /// Redirect the client with Composite and render from its window pixmap.
+    Pixmap,
+}
+
+/// A `[[regions]]` entry, a rectangle of the client shown in a window of its own.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RegionConfig {
+    /// Shown as the label of the window.
+    pub name: String,
+    pub x: i16,
+    pub y: i16,
+    pub width: NonZeroU16,
+    pub height: NonZeroU16,
+    /// Size of the window relative to the region.
+    #[serde(default = "default_scale", deserialize_with = "deserialize_scale")]
+    pub scale: f32,
+    /// Where the window is placed, next to the thumbnail if unset.
+    #[serde(default)]
+    pub position: Option<(i16, i16)>,
+}
+
+fn default_scale() -> f32 {
+    1.0
+}
+
+fn deserialize_scale<'de, D: serde::Deserializer<'de>>(deserializer: D) -> Result<f32, D::Error> {
+    let scale = f32::deserialize(deserializer)?;
+    if scale.is_finite() && scale > 0.0 {
+        Ok(scale)
+    } else {
+        Err(serde::de::Error::custom(format!(
+            "scale must be a positive number, got {scale}"
+        )))
+    }
+}
+
+/// A `[characters."Name"]` section overriding the global thumbnail settings for one character.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CharacterConfig {
+    width: Option<u16>,
+    height: Option<u16>,
+    opacity: Option<u32>,
+    border_color: Option<Argb>,
+    text_x: Option<i16>,
+    text_y: Option<i16>,
+    text_foreground: Option<Argb>,
+    text_background: Option<Argb>,
+    /// Replaces the global `[[regions]]` for this character.
+    regions: Option<Vec<RegionConfig>>,
+}
+
+/// Contents of `config.toml`. Every key is optional, anything missing falls back to the defaults.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct FileConfig {
+    width: Option<u16>,
+    height: Option<u16>,
+    opacity: Option<u32>,
+    border_size: Option<u16>,
+    border_color: Option<Argb>,
+    text_x: Option<i16>,
+    text_y: Option<i16>,
+    text_foreground: Option<Argb>,
+    text_background: Option<Argb>,
+    hide_when_no_focus: Option<bool>,
+    output: Option<String>,
+    snap_threshold: Option<u16>,
+    snap_gap: Option<u16>,
+    snap_bypass_modifier: Option<Modifier>,
+    resize_modifier: Option<Modifier>,
+    zoom_width: Option<u16>,
+    zoom_delay: Option<u64>,
+    zoom_anchor: Option<Anchor>,
+    capture: Option<CaptureMode>,
+    fit: Option<FitMode>,
+    letterbox_color: Option<Argb>,
+    filter: Option<Filter>,
+    two_pass_downscale: Option<bool>,
+    regions: Vec<RegionConfig>,
+    fps: Option<u32>,
+    fps_focused: Option<u32>,
+    fps_background: Option<u32>,
+    layout: LayoutConfig,
+    hotkeys: HotkeyConfig,
+    hooks: HookConfig,
+    characters: HashMap<String, CharacterConfig>,
+}
+
+/// An ARGB color written either as an integer (`0x7FFF0000`) or as a string (`"0x7FFF0000"`).
+#[derive(Debug, Clone, Copy)]
+struct Argb(u32);
+
+impl<'de> Deserialize<'de> for Argb {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct Visitor;
+
+        impl serde::de::Visitor<'_> for Visitor {
+            type Value = Argb;
+
+            fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+                f.write_str("an ARGB color such as 0x7FFF0000")
+            }
+
+            fn visit_i64<E: serde::de::Error>(self, v: i64) -> Result<Argb, E> {
+                u32::try_from(v)
+                    .map(Argb)
+                    .map_err(|_| E::custom(format!("color {v:#x} does not fit in 32 bits")))
+            }
+
+            fn visit_str<E: serde::de::Error>(self, v: &str) -> Result<Argb, E> {
+                Config::parse_str::<u32>(v)
+                    .map(Argb)
+                    .ok_or_else(|| E::custom(format!("invalid color '{v}'")))
+            }
+        }
+
+        deserializer.deserialize_any(Visitor)
+    }
+}
+
+/// A modifier key written as `shift`, `ctrl`, `alt`, `super` or `none`.
+#[derive(Debug, Clone, Copy)]
+pub struct Modifier(pub ModMask);
+
+impl Modifier {
+    pub fn parse(name: &str) -> Option<Self> {
+        Some(Self(match name.trim().to_ascii_lowercase().as_str() {
+            "shift" => ModMask::SHIFT,
+            "ctrl" | "control" => ModMask::CONTROL,
+            "alt" | "mod1" => ModMask::M1,
+            "super" | "mod4" => ModMask::M4,
+            "none" => ModMask::from(0u16),
+            _ => return None,
+        }))
+    }
+
+    /// Whether this modifier is held in an event's `state` field.
+    pub fn held(&self, state: impl Into<u16>) -> bool {
+        state.into() & u16::from(self.0) != 0
+    }
+}
+
+impl<'de> Deserialize<'de> for Modifier {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let name = String::deserialize(deserializer)?;
+        Self::parse(&name).ok_or_else(|| {
+            serde::de::Error::custom(format!(
+                "unknown modifier '{name}', expected shift, ctrl, alt, super or none"
+            ))
+        })
+    }
+}
+
+/// How clients whose aspect ratio differs from the thumbnail's are fitted into it.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum FitMode {
+    /// Scale width and height independently, distorting the client.
+    #[default]
+    Stretch,
+    /// Show the whole client with bars on two sides.
+    Fit,
+    /// Fill the thumbnail and crop the parts of the client that don't fit.
+    Fill,
+    /// Keep the configured width and derive the height from the client.
+    AutoSize,
+}
+
+/// The Render filter clients are scaled down with.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub enum Filter {
+    #[default]
+    Nearest,
+    Bilinear,
+    Good,
+    Best,
+    /// A convolution kernel as rows of weights, normalized to sum up to 1.
+    Convolution(Vec<Vec<f32>>),
+}
+
+impl Filter {
+    pub fn name(&self) -> &'static [u8] {
+        match self {
+            Self::Nearest => b"nearest",
+            Self::Bilinear => b"bilinear",
+            Self::Good => b"good",
+            Self::Best => b"best",
+            Self::Convolution(_) => b"convolution",
+        }
+    }
+
+    /// Filter parameters: the kernel width and height followed by its weights.
+    pub fn params(&self) -> Vec<f32> {
+        let Self::Convolution(rows) = self else {
+            return Vec::new();
+        };
+        let sum = rows.iter().flatten().sum::<f32>();
+        let sum = if sum == 0.0 { 1.0 } else { sum };
+        [rows[0].len() as f32, rows.len() as f32]
+            .into_iter()
+            .chain(rows.iter().flatten().map(|x| x / sum))
+            .collect()
+    }
+}
+
+impl<'de> Deserialize<'de> for Filter {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct Visitor;
+
+        impl<'de> serde::de::Visitor<'de> for Visitor {
+            type Value = Filter;
+
+            fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+                f.write_str("nearest, bilinear, good, best or a convolution kernel such as [[1, 2, 1], [2, 4, 2], [1, 2, 1]]")
+            }
+
+            fn visit_str<E: serde::de::Error>(self, v: &str) -> Result<Filter, E> {
+                Ok(match v {
+                    "nearest" => Filter::Nearest,
+                    "bilinear" => Filter::Bilinear,
+                    "good" => Filter::Good,
+                    "best" => Filter::Best,
+                    _ => return Err(E::invalid_value(serde::de::Unexpected::Str(v), &self)),
+                })
+            }
+
+            fn visit_seq<A: serde::de::SeqAccess<'de>>(
+                self,
+                mut seq: A,
+            ) -> Result<Filter, A::Error> {
+                let mut rows = Vec::<Vec<f32>>::new();
+                while let Some(row) = seq.next_element()? {
+                    rows.push(row);
+                }
+                if rows.is_empty() || rows[0].is_empty() {
+                    return Err(serde::de::Error::custom("convolution kernel is empty"));
+                }
+                if rows.iter().any(|x| x.len() != rows[0].len()) {
+                    return Err(serde::de::Error::custom(
+                        "convolution kernel rows must have the same length",
+                    ));
+                }
+                Ok(Filter::Convolution(rows))
+            }
+        }
+
+        deserializer.deserialize_any(Visitor)
+    }
+}
+
+/// Resolves an XDG base directory, falling back to `$HOME/<fallback>` when the variable is unset.
+pub fn xdg_dir(var: &str, fallback: &str) -> Option<PathBuf> {
+    env::var_os(var)
+        .filter(|x| !x.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(fallback)))
+}
+
+impl Config {
+    fn parse_str<T: std::str::FromStr + TryFrom<u128>>(s: &str) -> Option<T> {
+        let s = s.trim();
+        if let Some(hex) = s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")) {
+            u128::from_str_radix(hex, 16)
+                .ok()
+                .and_then(|n| T::try_from(n).ok())
+        } else {
+            s.parse::<T>().ok()
+        }
+    }
+
+    fn parse_num<T: std::str::FromStr + TryFrom<u128>>(var: &str) -> Option<T> {
+        let s = env::var(var).ok()?;
+        let parsed = Self::parse_str(&s);
+        if parsed.is_none() {
+            error!("failed to parse '{var}' value='{s}'");
+        }
+        parsed
+    }
+
+    fn parse_enum<T: serde::de::DeserializeOwned>(var: &str) -> Option<T> {
+        let s = env::var(var).ok()?;
+        T::deserialize(StrDeserializer::<ValueError>::new(s.trim()))
+            .inspect_err(|e| error!("failed to parse '{var}' value='{s}': err={e}"))
+            .ok()
+    }
+
+    fn parse_modifier(var: &str) -> Option<Modifier> {
+        let s = env::var(var).ok()?;
+        let parsed = Modifier::parse(&s);
+        if parsed.is_none() {
+            error!("failed to parse '{var}' value='{s}'");
+        }
+        parsed
+    }
+
+    fn to_color(raw: u32) -> Color {
+        let a = ((raw >> 24) & 0xFF) as u16;
+        let r = ((raw >> 16) & 0xFF) as u16;
+        let g = ((raw >> 8) & 0xFF) as u16;
+        let b = (raw & 0xFF) as u16;
+
+        let scale = |v: u16| (v as f32 / u8::MAX as f32 * u16::MAX as f32) as u16;
+
+        Color {
+            red: scale(r),
+            green: scale(g),
+            blue: scale(b),
+            alpha: scale(a),
+        }
+    }
+
+    fn premultiply_argb32(argb: u32) -> u32 {
+        let a = (argb >> 24) & 0xFF;
+        let r = (argb >> 16) & 0xFF;
+        let g = (argb >> 8) & 0xFF;
+        let b = argb & 0xFF;
+
+        let r_p = r * a / 255;
+        let g_p = g * a / 255;
+        let b_p = b * a / 255;
+
+        (a << 24) | (r_p << 16) | (g_p << 8) | b_p
+    }
+
+    fn default_path() -> Option<PathBuf> {
+        xdg_dir("XDG_CONFIG_HOME", ".config").map(|dir| dir.join("eve-l-preview/config.toml"))
+    }
+
+    fn parse_file(path: &Path, text: &str) -> Result<FileConfig> {
+        let location = |err: &toml::de::Error| {
+            let Some(span) = err.span() else {
+                return path.display().to_string();
+            };
+            let before = &text[..span.start];
+            let line = before.matches('\n').count() + 1;
+            let column = before.len() - before.rfind('\n').map_or(0, |x| x + 1) + 1;
+            format!("{}:{line}:{column}", path.display())
+        };
+
+        let deserializer = toml::Deserializer::parse(text)
+            .map_err(|err| anyhow::anyhow!("{}: {}", location(&err), err.message()))?;
+        serde_path_to_error::deserialize(deserializer).map_err(|err| {
+            let key = err.path().to_string();
+            let inner = err.inner();
+            if key == "." {
+                anyhow::anyhow!("{}: {}", location(inner), inner.message())
+            } else {
+                anyhow::anyhow!("{}: key '{key}': {}", location(inner), inner.message())
+            }
+        })
+    }
+
+    fn read_file(path: &Path) -> Result<FileConfig> {
+        let text = std::fs::read_to_string(path)
+            .with_context(|| format!("failed to read config file '{}'", path.display()))?;
+        let file = Self::parse_file(path, &text)?;
+        info!("loaded config file: path={}", path.display());
+        Ok(file)
+    }
+
+    /// Resolves the thumbnail settings for `character_name`, applying its section if there is one.
+    pub fn for_character(&self, character_name: &str) -> ThumbnailConfig {
+        let mut config = self.thumbnail.clone();
+        if let Some(character) = self.characters.get(character_name) {
+            config.width = character.width.unwrap_or(config.width);
+            config.height = character.height.unwrap_or(config.height);
+            config.opacity = character.opacity.unwrap_or(config.opacity);
+            config.text_x = character.text_x.unwrap_or(config.text_x);
+            config.text_y = character.text_y.unwrap_or(config.text_y);
+            if let Some(color) = character.border_color {
+                config.border_color = Self::to_color(color.0);
+            }
+            if let Some(color) = character.text_foreground {
+                config.text_foreground = Self::premultiply_argb32(color.0);
+            }
+            if let Some(color) = character.text_background {
+                config.text_background = Self::premultiply_argb32(color.0);
+            }
+            if let Some(regions) = &character.regions {
+                config.regions = regions.clone();
+            }
+        }
+        config
+    }
+
+    /// Minimum time between two repaints of a thumbnail, zero if its frame rate is unlimited.
+    pub fn frame_interval(&self, focused: bool) -> Duration {
+        let fps = if focused {
+            self.fps_focused
+        } else {
+            self.fps_background
+        };
+        match fps {
+            0 => Duration::ZERO,
+            fps => Duration::from_secs(1) / fps,
+        }
+    }
+
+    /// Loads `path`, or the default XDG config file if it exists, and applies environment overrides.
+    pub fn load(path: Option<&Path>) -> Result<Self> {
+        let file = match path {
+            Some(path) => Self::read_file(path)?,
+            None => match Self::default_path() {
+                Some(path) if path.exists() => Self::read_file(&path)?,
+                _ => FileConfig::default(),
+            },
+        };
+
+        let fps = Self::parse_num("FPS").or(file.fps).unwrap_or(0);
+        Ok(Self {
+            thumbnail: ThumbnailConfig {
+                width: Self::parse_num("WIDTH").or(file.width).unwrap_or(240),
+                height: Self::parse_num("HEIGHT").or(file.height).unwrap_or(135),
+                opacity: Self::parse_num("OPACITY")
+                    .or(file.opacity)
+                    .unwrap_or(0xC0000000),
+                border_size: Self::parse_num("BORDER_SIZE")
+                    .or(file.border_size)
+                    .unwrap_or(5),
+                border_color: Self::parse_num("BORDER_COLOR")
+                    .or(file.border_color.map(|x| x.0))
+                    .map(Self::to_color)
+                    .unwrap_or(Color {
+                        red: 0xFFFF,
+                        green: 0,
+                        blue: 0,
+                        alpha: 0x7F00,
+                    }),
+                text_x: Self::parse_num("TEXT_X").or(file.text_x).unwrap_or(10),
+                text_y: Self::parse_num("TEXT_Y").or(file.text_y).unwrap_or(125),
+                text_foreground: Self::premultiply_argb32(
+                    Self::parse_num("TEXT_FOREGROUND")
+                        .or(file.text_foreground.map(|x| x.0))
+                        .unwrap_or(0xFF_FF_FF_FF),
+                ),
+                text_background: Self::premultiply_argb32(
+                    Self::parse_num("TEXT_BACKGROUND")
+                        .or(file.text_background.map(|x| x.0))
+                        .unwrap_or(0x7F_00_00_00),
+                ),
+                capture: Self::parse_enum("CAPTURE")
+                    .or(file.capture)
+                    .unwrap_or_default(),
+                fit: Self::parse_enum("FIT").or(file.fit).unwrap_or_default(),
+                letterbox_color: Self::to_color(
+                    Self::parse_num("LETTERBOX_COLOR")
+                        .or(file.letterbox_color.map(|x| x.0))
+                        .unwrap_or(0xFF_00_00_00),
+                ),
+                filter: Self::parse_enum("FILTER")
+                    .or(file.filter)
+                    .unwrap_or_default(),
+                two_pass_downscale: env::var("TWO_PASS_DOWNSCALE")
+                    .ok()
+                    .and_then(|x| x.parse().ok())
+                    .or(file.two_pass_downscale)
+                    .unwrap_or(false),
+                regions: file.regions,
+            },
+            hide_when_no_focus: env::var("HIDE_WHEN_NO_FOCUS")
+                .ok()
+                .and_then(|x| x.parse().ok())
+                .or(file.hide_when_no_focus)
+                .unwrap_or(false),
+            output: env::var("OUTPUT").ok().or(file.output),
+            snap_threshold: Self::parse_num("SNAP_THRESHOLD")
+                .or(file.snap_threshold)
+                .unwrap_or(15),
+            snap_gap: Self::parse_num("SNAP_GAP").or(file.snap_gap).unwrap_or(0),
+            snap_bypass_modifier: Self::parse_modifier("SNAP_BYPASS_MODIFIER")
+                .or(file.snap_bypass_modifier)
+                .unwrap_or(Modifier(ModMask::SHIFT)),
+            resize_modifier: Self::parse_modifier("RESIZE_MODIFIER")
+                .or(file.resize_modifier)
+                .unwrap_or(Modifier(ModMask::CONTROL)),
+            zoom_width: Self::parse_num("ZOOM_WIDTH")
+                .or(file.zoom_width)
+                .unwrap_or(0),
+            zoom_delay: Duration::from_millis(
+                Self::parse_num("ZOOM_DELAY")
+                    .or(file.zoom_delay)
+                    .unwrap_or(500),
+            ),
+            zoom_anchor: Self::parse_enum("ZOOM_ANCHOR")
+                .or(file.zoom_anchor)
+                .unwrap_or_default(),
+            fps_focused: Self::parse_num("FPS_FOCUSED")
+                .or(file.fps_focused)
+                .unwrap_or(fps),
+            fps_background: Self::parse_num("FPS_BACKGROUND")
+                .or(file.fps_background)
+                .unwrap_or(fps),
+            layout: file.layout,
+            hotkeys: file.hotkeys,
+            hooks: file.hooks,
+            characters: file.characters,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse_error(text: &str) -> String {
+        Config::parse_file(Path::new("eve.toml"), text)
+            .unwrap_err()
+            .to_string()
+    }
+
+    /// Loads `text` as the config file, `name` keeps the files of concurrently running tests apart.
+    fn load(name: &str, text: &str) -> Config {
+        let path =
+            env::temp_dir().join(format!("eve-l-preview-{}-{name}.toml", std::process::id()));
+        std::fs::write(&path, text).unwrap();
+        let config = Config::load(Some(&path));
+        std::fs::remove_file(&path).unwrap();
+        config.unwrap()
+    }
+
+    #[test]
+    fn character_overrides_take_precedence() {
+        let config = load(
+            "overrides",
+            "width = 300\nheight = 200\nopacity = 0x80000000\n\n\
+             [characters.Main]\nwidth = 480\nborder_color = \"0xFF00FF00\"\n",
+        );
+        let main = config.for_character("Main");
+        assert_eq!((main.width, main.height), (480, 200));
+        assert_eq!(main.opacity, 0x80000000);
+        assert_eq!(
+            (
+                main.border_color.red,
+                main.border_color.green,
+                main.border_color.alpha
+            ),
+            (0, 0xFFFF, 0xFFFF)
+        );
+
+        let other = config.for_character("Other");
+        assert_eq!((other.width, other.height), (300, 200));
+        assert_eq!(other.border_color.red, config.thumbnail.border_color.red);
+    }
+
+    #[test]
+    fn parses_valid_file() {
+        let file = Config::parse_file(
+            Path::new("eve.toml"),
+            "width = 320\nborder_color = \"0xFF00FF00\"\ntext_foreground = 0x7F000000\n",
+        )
+        .unwrap();
+        assert_eq!(file.width, Some(320));
+        assert_eq!(file.border_color.map(|x| x.0), Some(0xFF00FF00));
+        assert_eq!(file.text_foreground.map(|x| x.0), Some(0x7F000000));
+        assert_eq!(file.height, None);
+    }
+
+    #[test]
+    fn reports_syntax_error_location() {
+        assert!(parse_error("width = 320\nheight = \n").starts_with("eve.toml:2:10: "));
+    }
+
+    #[test]
+    fn reports_invalid_value_key() {
+        assert!(
+            parse_error("width = 320\n\nheight = -1\n")
+                .starts_with("eve.toml:3:10: key 'height': ")
+        );
+        assert!(
+            parse_error("border_color = \"red\"\n")
+                .starts_with("eve.toml:1:16: key 'border_color': ")
+        );
+    }
+
+    #[test]
+    fn reports_unknown_key() {
+        assert!(
+            parse_error("width = 320\nwidht = 100\n")
+                .starts_with("eve.toml:2:1: key 'widht': unknown field `widht`")
+        );
+    }
+
+    #[test]
+    fn convolution_params_are_normalized() {
+        assert!(Filter::Good.params().is_empty());
+        let kernel = Filter::Convolution(vec![
+            vec![1.0, 2.0, 1.0],
+            vec![2.0, 4.0, 2.0],
+            vec![1.0, 2.0, 1.0],
+        ]);
+        assert_eq!(
+            kernel.params(),
+            [
+                3.0, 3.0, 0.0625, 0.125, 0.0625, 0.125, 0.25, 0.125, 0.0625, 0.125, 0.0625
+            ]
+        );
+        // A kernel summing up to 0 is used as is.
+        let kernel = Filter::Convolution(vec![vec![-1.0, 1.0]]);
+        assert_eq!(kernel.params(), [2.0, 1.0, -1.0, 1.0]);
+    }
+
+    #[test]
+    fn parses_filters() {
+        let file = Config::parse_file(Path::new("eve.toml"), "filter = \"bilinear\"\n").unwrap();
+        assert_eq!(file.filter, Some(Filter::Bilinear));
+        let file =
+            Config::parse_file(Path::new("eve.toml"), "filter = [[1, 2], [3, 4]]\n").unwrap();
+        assert_eq!(
+            file.filter,
+            Some(Filter::Convolution(vec![vec![1.0, 2.0], vec![3.0, 4.0]]))
+        );
+        assert!(parse_error("filter = \"cubic\"\n").starts_with("eve.toml:1:10: key 'filter': "));
+        assert!(parse_error("filter = []\n").ends_with("convolution kernel is empty"));
+        assert!(
+            parse_error("filter = [[1, 2], [3]]\n")
+                .ends_with("convolution kernel rows must have the same length")
+        );
+    }
+}
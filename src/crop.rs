@@ -0,0 +1,208 @@
+use anyhow::Result;
+use x11rb::connection::Connection;
+use x11rb::protocol::render::{ConnectionExt as RenderExt, CreatePictureAux, PictOp, Picture};
+use x11rb::protocol::xproto::*;
+use x11rb::rust_connection::RustConnection;
+
+use crate::{InputState, Rect, Thumbnail, create_preview_window};
+
+/// Space between a thumbnail and the region windows placed next to it.
+const GAP: i16 = 10;
+/// Distance of the region name from the top left corner of its window.
+const LABEL_MARGIN: i16 = 5;
+
+/// Window showing one of the configured `[[regions]]` of the source.
+#[derive(Debug)]
+pub struct Crop {
+    pub name: String,
+    /// The region, in source coordinates.
+    source: Rect,
+    pub window: Window,
+    picture: Picture,
+    overlay_pixmap: Pixmap,
+    overlay_picture: Picture,
+    /// Geometry of the window, kept up to date while it is dragged.
+    pub rect: Rect,
+    /// The region has no position and its window moves along with the thumbnail until dragged.
+    pub attached: bool,
+    pub input_state: InputState,
+}
+
+impl Crop {
+    pub fn reposition(&mut self, conn: &RustConnection, x: i16, y: i16) -> Result<()> {
+        conn.configure_window(
+            self.window,
+            &ConfigureWindowAux::new().x(x as i32).y(y as i32),
+        )?;
+        conn.flush()?;
+        self.rect.x = x;
+        self.rect.y = y;
+        Ok(())
+    }
+}
+
+impl Thumbnail<'_> {
+    /// Creates a window for each configured region, replacing the existing ones.
+    pub fn create_crops(&mut self) -> Result<()> {
+        self.destroy_crops()?;
+        // Regions without a position are stacked to the right of the thumbnail.
+        let mut next_y = self.rect.y;
+        let mut crops = Vec::new();
+        for region in &self.config.regions {
+            let width = ((region.width.get() as f32 * region.scale).round() as u16).max(1);
+            let height = ((region.height.get() as f32 * region.scale).round() as u16).max(1);
+            let (x, y) = region.position.unwrap_or_else(|| {
+                let position = (self.rect.x + self.rect.width as i16 + GAP, next_y);
+                next_y += height as i16 + GAP;
+                position
+            });
+
+            let rect = Rect::new(x, y, width, height);
+            let window = create_preview_window(
+                self.conn,
+                self.atoms,
+                self.root,
+                rect,
+                self.config.opacity,
+                EventMask::BUTTON_PRESS | EventMask::BUTTON_RELEASE | EventMask::POINTER_MOTION,
+            )?;
+            if self.visible {
+                self.conn.map_window(window)?;
+            }
+
+            let picture = self.conn.generate_id()?;
+            self.conn.render_create_picture(
+                picture,
+                window,
+                self.dst_format,
+                &CreatePictureAux::new(),
+            )?;
+            let (overlay_pixmap, overlay_picture) =
+                Self::create_overlay(self.conn, self.formats, self.root, width, height)?;
+            crops.push(Crop {
+                name: region.name.clone(),
+                source: Rect::new(region.x, region.y, region.width.get(), region.height.get()),
+                window,
+                picture,
+                overlay_pixmap,
+                overlay_picture,
+                rect,
+                attached: region.position.is_none(),
+                input_state: InputState::default(),
+            });
+        }
+        self.crops = crops;
+        for crop in &self.crops {
+            self.label_crop(crop)?;
+        }
+        self.paint_crops(None)?;
+        Ok(())
+    }
+
+    /// Stacks the attached region windows to the right of the thumbnail again after it moved or
+    /// was resized, the same way `create_crops` places them.
+    pub fn place_crops(&mut self) -> Result<()> {
+        let x = self.rect.x + self.rect.width as i16 + GAP;
+        let mut next_y = self.rect.y;
+        for crop in self.crops.iter_mut().filter(|x| x.attached) {
+            crop.reposition(self.conn, x, next_y)?;
+            next_y += crop.rect.height as i16 + GAP;
+        }
+        Ok(())
+    }
+
+    pub fn destroy_crops(&mut self) -> Result<()> {
+        for crop in self.crops.drain(..) {
+            self.conn.render_free_picture(crop.overlay_picture)?;
+            self.conn.free_pixmap(crop.overlay_pixmap)?;
+            self.conn.render_free_picture(crop.picture)?;
+            self.conn.destroy_window(crop.window)?;
+        }
+        Ok(())
+    }
+
+    /// Draws the region name into the top left corner of the crop's overlay.
+    pub fn label_crop(&self, crop: &Crop) -> Result<()> {
+        self.conn.render_composite(
+            PictOp::CLEAR,
+            crop.overlay_picture,
+            0u32,
+            crop.overlay_picture,
+            0,
+            0,
+            0,
+            0,
+            0,
+            0,
+            crop.rect.width,
+            crop.rect.height,
+        )?;
+        self.conn.image_text8(
+            crop.overlay_pixmap,
+            self.overlay_gc,
+            LABEL_MARGIN,
+            LABEL_MARGIN + self.font_ascent,
+            crop.name.as_bytes(),
+        )?;
+        Ok(())
+    }
+
+    /// Repaints the region windows, or only those overlapping the `damaged` parts of the source.
+    pub fn paint_crops(&self, damaged: Option<&[Rect]>) -> Result<()> {
+        let mut painted = false;
+        for crop in &self.crops {
+            if let Some(damaged) = damaged
+                && !damaged.iter().any(|x| x.intersect(&crop.source).is_some())
+            {
+                continue;
+            }
+            // Like the zoom preview, each region borrows `src_picture` with a scale of its own.
+            self.set_scaling(
+                self.src_picture,
+                (crop.source.width, crop.source.height),
+                (crop.rect.width, crop.rect.height),
+            )?;
+            let op = self.background(
+                crop.picture,
+                Rect::new(0, 0, crop.rect.width, crop.rect.height),
+                false,
+            )?;
+            // The source offset goes through the transform, so it is given at the crop's scale.
+            let src_x = crop.source.x as f32 * crop.rect.width as f32 / crop.source.width as f32;
+            let src_y = crop.source.y as f32 * crop.rect.height as f32 / crop.source.height as f32;
+            self.conn.render_composite(
+                op,
+                self.src_picture,
+                0u32,
+                crop.picture,
+                src_x.round() as i16,
+                src_y.round() as i16,
+                0,
+                0,
+                0,
+                0,
+                crop.rect.width,
+                crop.rect.height,
+            )?;
+            self.conn.render_composite(
+                PictOp::OVER,
+                crop.overlay_picture,
+                0u32,
+                crop.picture,
+                0,
+                0,
+                0,
+                0,
+                0,
+                0,
+                crop.rect.width,
+                crop.rect.height,
+            )?;
+            painted = true;
+        }
+        if painted {
+            self.scale_source()?;
+        }
+        Ok(())
+    }
+}
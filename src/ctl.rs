@@ -0,0 +1,88 @@
+use anyhow::Result;
+use serde::Deserialize;
+use serde::de::value::{Error as ValueError, StrDeserializer};
+use std::io::Write;
+
+use crate::ipc::{self, Request};
+use crate::layout::LayoutMode;
+
+const USAGE: &str = "usage: eve-l-preview ctl <command>
+
+commands:
+    list [--json]                          list tracked clients
+    focus <character>                      focus a character's client
+    move <character> <x> <y>               move a thumbnail
+    resize <character> <width> <height>    resize a thumbnail
+    show                                   show all thumbnails
+    hide                                   hide all thumbnails
+    reload                                 reload the config file
+    layout apply <mode>                    switch to the manual, grid, row or column layout
+    subscribe                              print client events as JSON lines until the instance exits";
+
+fn parse<T: std::str::FromStr>(name: &str, value: &str) -> Result<T> {
+    value
+        .parse()
+        .map_err(|_| anyhow::anyhow!("invalid <{name}> '{value}'"))
+}
+
+/// Runs `eve-l-preview ctl ...` against the running instance and prints the result.
+pub fn run(args: Vec<String>) -> Result<()> {
+    let args = args.iter().map(String::as_str).collect::<Vec<_>>();
+    let json = args == ["list", "--json"];
+
+    let request = match args.as_slice() {
+        ["list"] | ["list", "--json"] => Request::List,
+        ["focus", character] => Request::Focus {
+            character: character.to_string(),
+        },
+        ["move", character, x, y] => Request::Move {
+            character: character.to_string(),
+            x: parse("x", x)?,
+            y: parse("y", y)?,
+        },
+        ["resize", character, width, height] => Request::Resize {
+            character: character.to_string(),
+            width: parse("width", width)?,
+            height: parse("height", height)?,
+        },
+        ["show"] => Request::Show,
+        ["hide"] => Request::Hide,
+        ["reload"] => Request::Reload,
+        ["layout", "apply", mode] => Request::Layout {
+            mode: LayoutMode::deserialize(StrDeserializer::<ValueError>::new(mode))?,
+        },
+        ["subscribe"] => {
+            return ipc::subscribe(|line| Ok(writeln!(std::io::stdout(), "{line}")?));
+        }
+        _ => anyhow::bail!("{USAGE}"),
+    };
+
+    let (response, line) = ipc::send(&request)?;
+    if !response.ok {
+        anyhow::bail!(response.error.unwrap_or_default());
+    }
+
+    if json {
+        println!("{line}");
+    } else if let Some(clients) = response.clients {
+        for client in clients {
+            let mut flags = Vec::new();
+            if client.focused {
+                flags.push("focused");
+            }
+            if client.minimized {
+                flags.push("minimized");
+            }
+            if !client.visible {
+                flags.push("hidden");
+            }
+            let character = if client.character.is_empty() {
+                "(character select)"
+            } else {
+                &client.character
+            };
+            println!("{character}\t{:#x}\t{}", client.window, flags.join(","));
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,115 @@
+use anyhow::Result;
+use rustix::event::{PollFd, PollFlags, Timespec};
+use std::os::fd::BorrowedFd;
+use std::time::Instant;
+
+/// Deadlines for work that isn't triggered by an X event, keyed by `T`.
+#[derive(Debug)]
+pub struct Timers<T> {
+    deadlines: Vec<(Instant, T)>,
+}
+
+impl<T> Default for Timers<T> {
+    fn default() -> Self {
+        Self {
+            deadlines: Vec::new(),
+        }
+    }
+}
+
+impl<T: PartialEq> Timers<T> {
+    /// Schedules `timer` at `at`. A timer that is already scheduled keeps the earlier deadline.
+    pub fn schedule(&mut self, at: Instant, timer: T) {
+        match self.deadlines.iter_mut().find(|(_, x)| *x == timer) {
+            Some((deadline, _)) => *deadline = (*deadline).min(at),
+            None => self.deadlines.push((at, timer)),
+        }
+    }
+
+    /// Unschedules `timer`, if it is scheduled.
+    pub fn cancel(&mut self, timer: &T) {
+        self.deadlines.retain(|(_, x)| x != timer);
+    }
+
+    /// The earliest deadline, if any timer is scheduled.
+    pub fn next(&self) -> Option<Instant> {
+        self.deadlines.iter().map(|(x, _)| *x).min()
+    }
+
+    /// Removes and returns every timer whose deadline has passed.
+    pub fn expire(&mut self, now: Instant) -> Vec<T> {
+        let mut expired = Vec::new();
+        let mut i = 0;
+        while i < self.deadlines.len() {
+            if self.deadlines[i].0 <= now {
+                expired.push(self.deadlines.swap_remove(i).1);
+            } else {
+                i += 1;
+            }
+        }
+        expired
+    }
+}
+
+/// Blocks until one of `fds` is readable or `deadline` has passed. Signals just end the wait early.
+pub fn wait<'a>(
+    fds: impl IntoIterator<Item = BorrowedFd<'a>>,
+    deadline: Option<Instant>,
+) -> Result<()> {
+    let mut fds = fds
+        .into_iter()
+        .map(|fd| PollFd::from_borrowed_fd(fd, PollFlags::IN))
+        .collect::<Vec<_>>();
+    let timeout = deadline
+        .map(|x| Timespec::try_from(x.saturating_duration_since(Instant::now())))
+        .transpose()?;
+    match rustix::event::poll(&mut fds, timeout.as_ref()) {
+        Ok(_) | Err(rustix::io::Errno::INTR) => Ok(()),
+        Err(e) => Err(e.into()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn schedule_keeps_earlier_deadline() {
+        let now = Instant::now();
+        let mut timers = Timers::default();
+        timers.schedule(now + Duration::from_millis(20), 1);
+        timers.schedule(now + Duration::from_millis(10), 1);
+        timers.schedule(now + Duration::from_millis(30), 1);
+        assert_eq!(timers.next(), Some(now + Duration::from_millis(10)));
+        assert_eq!(timers.expire(now + Duration::from_millis(10)), [1]);
+        assert_eq!(timers.next(), None);
+    }
+
+    #[test]
+    fn expire_returns_only_passed_timers() {
+        let now = Instant::now();
+        let mut timers = Timers::default();
+        timers.schedule(now + Duration::from_millis(30), 3);
+        timers.schedule(now + Duration::from_millis(10), 1);
+        timers.schedule(now + Duration::from_millis(20), 2);
+        assert!(timers.expire(now).is_empty());
+
+        let mut expired = timers.expire(now + Duration::from_millis(20));
+        expired.sort();
+        assert_eq!(expired, [1, 2]);
+        assert_eq!(timers.next(), Some(now + Duration::from_millis(30)));
+    }
+
+    #[test]
+    fn cancel_unschedules() {
+        let now = Instant::now();
+        let mut timers = Timers::default();
+        timers.schedule(now, 1);
+        timers.schedule(now + Duration::from_millis(10), 2);
+        timers.cancel(&1);
+        timers.cancel(&3);
+        assert_eq!(timers.next(), Some(now + Duration::from_millis(10)));
+        assert_eq!(timers.expire(now + Duration::from_millis(10)), [2]);
+    }
+}
@@ -0,0 +1,160 @@
+use serde::Deserialize;
+use std::process::{Child, Command, Stdio};
+use tracing::{debug, error, warn};
+
+use crate::ipc::ClientEvent;
+
+/// The `[hooks]` section of the config file, a shell command per client event.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct HookConfig {
+    pub added: Option<String>,
+    pub removed: Option<String>,
+    pub focus_in: Option<String>,
+    pub focus_out: Option<String>,
+    pub minimized: Option<String>,
+    pub restored: Option<String>,
+    pub renamed: Option<String>,
+    pub moved: Option<String>,
+}
+
+impl HookConfig {
+    fn command(&self, event: &ClientEvent) -> Option<&str> {
+        match event {
+            ClientEvent::Added { .. } => &self.added,
+            ClientEvent::Removed { .. } => &self.removed,
+            ClientEvent::FocusIn { .. } => &self.focus_in,
+            ClientEvent::FocusOut { .. } => &self.focus_out,
+            ClientEvent::Minimized { .. } => &self.minimized,
+            ClientEvent::Restored { .. } => &self.restored,
+            ClientEvent::Renamed { .. } => &self.renamed,
+            ClientEvent::Moved { .. } => &self.moved,
+        }
+        .as_deref()
+    }
+}
+
+/// Hook commands that have been spawned but not reaped yet.
+#[derive(Debug, Default)]
+pub struct Hooks {
+    children: Vec<Child>,
+}
+
+impl Hooks {
+    /// Spawns the hook configured for `event` through `sh -c` without waiting for it.
+    pub fn run(&mut self, config: &HookConfig, event: &ClientEvent, pid: Option<u32>) {
+        let Some(command) = config.command(event) else {
+            return;
+        };
+        let (ClientEvent::Added { window, character }
+        | ClientEvent::Removed { window, character }
+        | ClientEvent::FocusIn { window, character }
+        | ClientEvent::FocusOut { window, character }
+        | ClientEvent::Minimized { window, character }
+        | ClientEvent::Restored { window, character }
+        | ClientEvent::Renamed {
+            window, character, ..
+        }
+        | ClientEvent::Moved {
+            window, character, ..
+        }) = event;
+
+        let mut cmd = Command::new("sh");
+        cmd.arg("-c")
+            .arg(command)
+            .stdin(Stdio::null())
+            .env("EVE_CHARACTER", character)
+            .env("EVE_WINDOW", window.to_string())
+            .env("EVE_PID", pid.map(|x| x.to_string()).unwrap_or_default());
+        if let ClientEvent::Renamed { previous, .. } = event {
+            cmd.env("EVE_PREVIOUS_CHARACTER", previous);
+        }
+        match cmd.spawn() {
+            Ok(child) => {
+                debug!("spawned hook: pid={} command={command}", child.id());
+                self.children.push(child);
+            }
+            Err(e) => error!("failed to spawn hook: command={command} err={e}"),
+        }
+    }
+
+    /// Whether spawned hooks haven't been reaped yet.
+    pub fn running(&self) -> bool {
+        !self.children.is_empty()
+    }
+
+    /// Collects hooks that have exited so that they don't linger as zombies.
+    pub fn reap(&mut self) {
+        self.children.retain_mut(|child| match child.try_wait() {
+            Ok(Some(status)) => {
+                if !status.success() {
+                    warn!("hook exited unsuccessfully: pid={} {status}", child.id());
+                }
+                false
+            }
+            Ok(None) => true,
+            Err(e) => {
+                error!("failed to wait for hook: pid={} err={e}", child.id());
+                false
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn reap_collects_exited_hooks() {
+        let config = HookConfig {
+            added: Some("exit 3".into()),
+            removed: Some("sleep 10".into()),
+            ..Default::default()
+        };
+        let mut hooks = Hooks::default();
+        let character = String::from("Main");
+        hooks.run(
+            &config,
+            &ClientEvent::FocusIn {
+                window: 1,
+                character: character.clone(),
+            },
+            None,
+        );
+        assert!(hooks.children.is_empty());
+
+        hooks.run(
+            &config,
+            &ClientEvent::Added {
+                window: 1,
+                character: character.clone(),
+            },
+            None,
+        );
+        hooks.run(
+            &config,
+            &ClientEvent::Removed {
+                window: 1,
+                character,
+            },
+            Some(42),
+        );
+        assert_eq!(hooks.children.len(), 2);
+
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while hooks.children.len() > 1 && Instant::now() < deadline {
+            hooks.reap();
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        // Only the hook that is still sleeping is left.
+        assert_eq!(hooks.children.len(), 1);
+        assert!(hooks.running());
+
+        hooks.children[0].kill().unwrap();
+        hooks.children[0].wait().unwrap();
+        hooks.reap();
+        assert!(!hooks.running());
+    }
+}
@@ -0,0 +1,336 @@
+use anyhow::Result;
+use serde::Deserialize;
+use std::collections::HashMap;
+use tracing::{error, info, warn};
+use x11rb::connection::Connection;
+use x11rb::protocol::xproto::{
+    ConnectionExt, Grab, GrabMode, KeyPressEvent, Keycode, ModMask, Screen,
+};
+use x11rb::rust_connection::RustConnection;
+
+use crate::config::Modifier;
+use crate::layout::Order;
+
+/// A key combination such as `ctrl+shift+Tab`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KeyCombo {
+    modifiers: u16,
+    keysym: u32,
+}
+
+/// Maps a key name to its keysym. Covers the keys that make sense for global hotkeys.
+fn keysym_from_name(name: &str) -> Option<u32> {
+    let mut chars = name.chars();
+    if let (Some(c), None) = (chars.next(), chars.next())
+        && c.is_ascii_graphic()
+    {
+        return Some(c.to_ascii_lowercase() as u32);
+    }
+    if let Some(n) = name.strip_prefix('F').and_then(|x| x.parse::<u32>().ok())
+        && (1..=35).contains(&n)
+    {
+        return Some(0xFFBE + n - 1);
+    }
+    if let Some(n) = name.strip_prefix("KP_").and_then(|x| x.parse::<u32>().ok())
+        && n <= 9
+    {
+        return Some(0xFFB0 + n);
+    }
+    Some(match name {
+        "space" => 0x0020,
+        "Tab" => 0xFF09,
+        "Return" => 0xFF0D,
+        "Escape" => 0xFF1B,
+        "BackSpace" => 0xFF08,
+        "Delete" => 0xFFFF,
+        "Insert" => 0xFF63,
+        "Home" => 0xFF50,
+        "End" => 0xFF57,
+        "Prior" | "Page_Up" => 0xFF55,
+        "Next" | "Page_Down" => 0xFF56,
+        "Left" => 0xFF51,
+        "Up" => 0xFF52,
+        "Right" => 0xFF53,
+        "Down" => 0xFF54,
+        "grave" => 0x0060,
+        "minus" => 0x002D,
+        "equal" => 0x003D,
+        "bracketleft" => 0x005B,
+        "bracketright" => 0x005D,
+        "semicolon" => 0x003B,
+        "apostrophe" => 0x0027,
+        "comma" => 0x002C,
+        "period" => 0x002E,
+        "slash" => 0x002F,
+        "backslash" => 0x005C,
+        _ => return None,
+    })
+}
+
+impl std::str::FromStr for KeyCombo {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = s.split('+').map(str::trim).collect::<Vec<_>>();
+        let Some(key) = parts.pop().filter(|x| !x.is_empty()) else {
+            return Err(format!("missing key in '{s}'"));
+        };
+        let mut modifiers = 0;
+        for part in parts {
+            let Some(modifier) = Modifier::parse(part) else {
+                return Err(format!(
+                    "unknown modifier '{part}' in '{s}', expected shift, ctrl, alt or super"
+                ));
+            };
+            modifiers |= u16::from(modifier.0);
+        }
+        let keysym = keysym_from_name(key).ok_or_else(|| format!("unknown key '{key}'"))?;
+        Ok(Self { modifiers, keysym })
+    }
+}
+
+impl<'de> Deserialize<'de> for KeyCombo {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(serde::de::Error::custom)
+    }
+}
+
+/// The `[hotkeys]` section of the config file.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct HotkeyConfig {
+    pub cycle_forward: Option<KeyCombo>,
+    pub cycle_backward: Option<KeyCombo>,
+    /// Order in which `cycle_forward` walks through the clients.
+    pub order: Order,
+    /// Hotkeys focusing a specific character, keyed by character name.
+    pub characters: HashMap<String, KeyCombo>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Action {
+    CycleForward,
+    CycleBackward,
+    Focus(String),
+}
+
+#[derive(Debug)]
+struct Binding {
+    keycode: Keycode,
+    modifiers: u16,
+    action: Action,
+}
+
+/// Key grabs on the root window and the actions they trigger.
+#[derive(Debug, Default)]
+pub struct Hotkeys {
+    bindings: Vec<Binding>,
+}
+
+impl Hotkeys {
+    /// Grabs every configured hotkey on the root window.
+    /// Combinations that cannot be resolved or are grabbed by another client are logged and skipped.
+    pub fn grab(conn: &RustConnection, screen: &Screen, config: &HotkeyConfig) -> Result<Self> {
+        let combos = [
+            (config.cycle_forward, Action::CycleForward),
+            (config.cycle_backward, Action::CycleBackward),
+        ];
+        let combos = combos
+            .into_iter()
+            .filter_map(|(combo, action)| Some((combo?, action)))
+            .chain(
+                config
+                    .characters
+                    .iter()
+                    .map(|(name, combo)| (*combo, Action::Focus(name.clone()))),
+            )
+            .collect::<Vec<_>>();
+        if combos.is_empty() {
+            return Ok(Self::default());
+        }
+
+        let setup = conn.setup();
+        let mapping = conn
+            .get_keyboard_mapping(setup.min_keycode, setup.max_keycode - setup.min_keycode + 1)?
+            .reply()?;
+        let per_keycode = mapping.keysyms_per_keycode.max(1) as usize;
+        let keycode_of = |keysym: u32| {
+            mapping
+                .keysyms
+                .chunks(per_keycode)
+                .position(|x| x.contains(&keysym))
+                .map(|x| setup.min_keycode + x as u8)
+        };
+
+        let mut bindings = Vec::new();
+        for (combo, action) in combos {
+            let Some(keycode) = keycode_of(combo.keysym) else {
+                warn!(
+                    "no keycode for hotkey: keysym={:#x} action={action:?}",
+                    combo.keysym
+                );
+                continue;
+            };
+            if let Some(other) = bindings
+                .iter()
+                .find(|x: &&Binding| x.keycode == keycode && x.modifiers == combo.modifiers)
+            {
+                warn!(
+                    "hotkey is already bound, ignoring it: action={action:?} bound_to={:?}",
+                    other.action
+                );
+                continue;
+            }
+            // Grab every Caps Lock and Num Lock combination so that they don't affect the hotkey.
+            let (caps_lock, num_lock) = (u16::from(ModMask::LOCK), u16::from(ModMask::M2));
+            let mut grabbed = true;
+            for ignored in [0, caps_lock, num_lock, caps_lock | num_lock] {
+                let modifiers = ModMask::from(combo.modifiers | ignored);
+                if let Err(e) = conn
+                    .grab_key(
+                        false,
+                        screen.root,
+                        modifiers,
+                        keycode,
+                        GrabMode::ASYNC,
+                        GrabMode::ASYNC,
+                    )?
+                    .check()
+                {
+                    error!(
+                        "failed to grab hotkey, is it used by another program? action={action:?} err={e:?}"
+                    );
+                    grabbed = false;
+                    break;
+                }
+            }
+            if grabbed {
+                info!("grabbed hotkey: keycode={keycode} action={action:?}");
+                bindings.push(Binding {
+                    keycode,
+                    modifiers: combo.modifiers,
+                    action,
+                });
+            }
+        }
+        conn.flush()?;
+        Ok(Self { bindings })
+    }
+
+    /// Releases every key grab, e.g. before grabbing the hotkeys of a reloaded config.
+    pub fn ungrab(&mut self, conn: &RustConnection, screen: &Screen) -> Result<()> {
+        conn.ungrab_key(Grab::ANY, screen.root, ModMask::ANY)?;
+        self.bindings.clear();
+        Ok(())
+    }
+
+    /// The action bound to a key press, ignoring Caps Lock and Num Lock.
+    pub fn action(&self, event: &KeyPressEvent) -> Option<&Action> {
+        let ignored = u16::from(ModMask::LOCK | ModMask::M2);
+        let state = u16::from(event.state) & !ignored & 0xFF;
+        self.bindings
+            .iter()
+            .find(|x| x.keycode == event.detail && x.modifiers == state)
+            .map(|x| &x.action)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_modifiers() {
+        let combo = "ctrl+shift+Tab".parse::<KeyCombo>().unwrap();
+        assert_eq!(
+            combo.modifiers,
+            u16::from(ModMask::CONTROL | ModMask::SHIFT)
+        );
+        assert_eq!(combo.keysym, 0xFF09);
+        assert_eq!(
+            "Ctrl + ALT + Tab".parse::<KeyCombo>().unwrap().modifiers,
+            u16::from(ModMask::CONTROL | ModMask::M1)
+        );
+        assert_eq!("F5".parse::<KeyCombo>().unwrap().modifiers, 0);
+    }
+
+    #[test]
+    fn letters_ignore_case() {
+        assert_eq!("super+A".parse::<KeyCombo>(), "super+a".parse::<KeyCombo>());
+        assert_eq!("a".parse::<KeyCombo>().unwrap().keysym, 'a' as u32);
+    }
+
+    #[test]
+    fn named_keys() {
+        assert_eq!(keysym_from_name("F1"), Some(0xFFBE));
+        assert_eq!(keysym_from_name("F12"), Some(0xFFC9));
+        assert_eq!(keysym_from_name("KP_7"), Some(0xFFB7));
+        assert_eq!(keysym_from_name("Page_Up"), keysym_from_name("Prior"));
+        assert_eq!(keysym_from_name("F0"), None);
+        assert_eq!(keysym_from_name("F36"), None);
+        // Names other than single letters are case sensitive, like X keysym names.
+        assert_eq!(keysym_from_name("tab"), None);
+    }
+
+    #[test]
+    fn parses_character_hotkeys() {
+        let config: HotkeyConfig = toml::from_str(
+            "cycle_forward = \"ctrl+Tab\"\n\n[characters]\nMain = \"F1\"\n\"Scout Alt\" = \"shift+F2\"\n",
+        )
+        .unwrap();
+        assert_eq!(config.characters.len(), 2);
+        assert_eq!(config.characters["Main"], "F1".parse().unwrap());
+        assert_eq!(config.characters["Scout Alt"], "shift+F2".parse().unwrap());
+    }
+
+    #[test]
+    fn action_ignores_lock_modifiers() {
+        let hotkeys = Hotkeys {
+            bindings: vec![
+                Binding {
+                    keycode: 67,
+                    modifiers: 0,
+                    action: Action::Focus("Main".into()),
+                },
+                Binding {
+                    keycode: 68,
+                    modifiers: u16::from(ModMask::SHIFT),
+                    action: Action::Focus("Scout Alt".into()),
+                },
+            ],
+        };
+        let press = |detail, state: ModMask| KeyPressEvent {
+            detail,
+            state: u16::from(state).into(),
+            ..Default::default()
+        };
+        assert_eq!(
+            hotkeys.action(&press(67, ModMask::LOCK | ModMask::M2)),
+            Some(&Action::Focus("Main".into()))
+        );
+        assert_eq!(
+            hotkeys.action(&press(68, ModMask::SHIFT)),
+            Some(&Action::Focus("Scout Alt".into()))
+        );
+        assert_eq!(hotkeys.action(&press(68, ModMask::from(0u16))), None);
+        assert_eq!(hotkeys.action(&press(67, ModMask::CONTROL)), None);
+    }
+
+    #[test]
+    fn rejects_unknown_parts() {
+        assert_eq!(
+            "hyper+a".parse::<KeyCombo>(),
+            Err("unknown modifier 'hyper' in 'hyper+a', expected shift, ctrl, alt or super".into())
+        );
+        assert_eq!(
+            "ctrl+Foo".parse::<KeyCombo>(),
+            Err("unknown key 'Foo'".into())
+        );
+        assert_eq!(
+            "ctrl+".parse::<KeyCombo>(),
+            Err("missing key in 'ctrl+'".into())
+        );
+    }
+}
@@ -0,0 +1,370 @@
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::io::{BufRead, BufReader, ErrorKind, Read, Write};
+use std::os::fd::{AsFd, BorrowedFd};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::PathBuf;
+use std::time::Duration;
+use tracing::{debug, info, warn};
+
+use crate::layout::LayoutMode;
+use crate::runtime_dir;
+
+/// Location of the control socket, `$XDG_RUNTIME_DIR/eve-l-preview.sock`.
+pub fn socket_path() -> Result<PathBuf> {
+    runtime_dir()
+        .map(|x| x.join("eve-l-preview.sock"))
+        .context("XDG_RUNTIME_DIR is not set")
+}
+
+/// A command sent by a client, one JSON object per line, e.g. `{"cmd":"focus","character":"Name"}`.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "cmd", rename_all = "snake_case")]
+pub enum Request {
+    List,
+    Focus {
+        character: String,
+    },
+    Move {
+        character: String,
+        x: i16,
+        y: i16,
+    },
+    Resize {
+        character: String,
+        width: u16,
+        height: u16,
+    },
+    Show,
+    Hide,
+    Reload,
+    Layout {
+        mode: LayoutMode,
+    },
+    /// Keeps the connection open and streams every [`ClientEvent`] to it.
+    Subscribe,
+}
+
+/// A change to a client's state, sent to subscribed clients as one JSON object per line,
+/// e.g. `{"event":"focus_in","window":4194310,"character":"Name"}`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum ClientEvent {
+    Added {
+        window: u32,
+        character: String,
+    },
+    Removed {
+        window: u32,
+        character: String,
+    },
+    FocusIn {
+        window: u32,
+        character: String,
+    },
+    FocusOut {
+        window: u32,
+        character: String,
+    },
+    Minimized {
+        window: u32,
+        character: String,
+    },
+    Restored {
+        window: u32,
+        character: String,
+    },
+    Renamed {
+        window: u32,
+        character: String,
+        previous: String,
+    },
+    Moved {
+        window: u32,
+        character: String,
+        x: i16,
+        y: i16,
+    },
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ClientInfo {
+    pub window: u32,
+    pub character: String,
+    pub focused: bool,
+    pub minimized: bool,
+    pub visible: bool,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Response {
+    pub ok: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub clients: Option<Vec<ClientInfo>>,
+}
+
+impl Response {
+    pub fn ok() -> Self {
+        Self {
+            ok: true,
+            ..Default::default()
+        }
+    }
+
+    pub fn error(error: impl std::fmt::Display) -> Self {
+        Self {
+            ok: false,
+            error: Some(error.to_string()),
+            ..Default::default()
+        }
+    }
+}
+
+fn request(request: &Request) -> Result<BufReader<UnixStream>> {
+    let path = socket_path()?;
+    let mut stream = UnixStream::connect(&path).with_context(|| {
+        format!(
+            "failed to connect to '{}', is eve-l-preview running?",
+            path.display()
+        )
+    })?;
+    let mut line = serde_json::to_vec(request)?;
+    line.push(b'\n');
+    stream.write_all(&line)?;
+    Ok(BufReader::new(stream))
+}
+
+/// Reads a response, returning it parsed and as the line it was sent as.
+fn read_response(reader: &mut BufReader<UnixStream>) -> Result<(Response, String)> {
+    let mut line = String::new();
+    reader.read_line(&mut line)?;
+    let response = serde_json::from_str(&line).context("invalid response from eve-l-preview")?;
+    line.truncate(line.trim_end().len());
+    Ok((response, line))
+}
+
+/// Sends a single request to the running instance and waits for its response, which is also
+/// returned as the raw JSON line.
+pub fn send(request: &Request) -> Result<(Response, String)> {
+    read_response(&mut self::request(request)?)
+}
+
+/// Subscribes to the running instance and calls `f` with every event line until it exits.
+pub fn subscribe(mut f: impl FnMut(&str) -> Result<()>) -> Result<()> {
+    let mut reader = request(&Request::Subscribe)?;
+    let (response, _) = read_response(&mut reader)?;
+    if !response.ok {
+        anyhow::bail!(response.error.unwrap_or_default());
+    }
+    for line in reader.lines() {
+        f(&line?)?;
+    }
+    Ok(())
+}
+
+/// Longest request line a client may send before it is disconnected.
+const MAX_LINE: usize = 64 * 1024;
+/// How long a reply may wait for a client to read the previous ones before it is disconnected.
+const REPLY_TIMEOUT: Duration = Duration::from_secs(1);
+
+#[derive(Debug)]
+struct Client {
+    stream: UnixStream,
+    buffer: Vec<u8>,
+    subscribed: bool,
+}
+
+impl Client {
+    /// Writes a whole reply line. A non-blocking write could stop after a part of it, so the
+    /// stream blocks for the reply, up to `REPLY_TIMEOUT`.
+    fn reply(&mut self, line: &[u8]) -> std::io::Result<()> {
+        self.stream.set_nonblocking(false)?;
+        let result = self.stream.write_all(line);
+        self.stream.set_nonblocking(true)?;
+        result
+    }
+}
+
+/// Non-blocking control socket serviced from the main loop.
+#[derive(Debug)]
+pub struct Server {
+    listener: UnixListener,
+    path: PathBuf,
+    clients: Vec<Client>,
+}
+
+impl Server {
+    /// Binds the control socket, replacing a stale socket file left behind by a crashed instance.
+    pub fn bind() -> Result<Self> {
+        let path = socket_path()?;
+        if path.exists() {
+            if UnixStream::connect(&path).is_ok() {
+                anyhow::bail!(
+                    "another instance is already listening on '{}'",
+                    path.display()
+                );
+            }
+            std::fs::remove_file(&path)
+                .with_context(|| format!("failed to remove stale socket '{}'", path.display()))?;
+        }
+        let listener = UnixListener::bind(&path)
+            .with_context(|| format!("failed to bind '{}'", path.display()))?;
+        listener.set_nonblocking(true)?;
+        info!("listening for control commands: path={}", path.display());
+        Ok(Self {
+            listener,
+            path,
+            clients: Vec::new(),
+        })
+    }
+
+    /// File descriptors the main loop has to wait on.
+    pub fn fds(&self) -> Vec<BorrowedFd<'_>> {
+        std::iter::once(self.listener.as_fd())
+            .chain(self.clients.iter().map(|x| x.stream.as_fd()))
+            .collect()
+    }
+
+    /// Accepts pending connections and answers every complete request line with `handler`.
+    pub fn service(&mut self, mut handler: impl FnMut(Request) -> Response) {
+        loop {
+            match self.listener.accept() {
+                Ok((stream, _)) => {
+                    if let Err(e) = stream
+                        .set_nonblocking(true)
+                        .and_then(|()| stream.set_write_timeout(Some(REPLY_TIMEOUT)))
+                    {
+                        warn!("failed to set up control client socket: err={e}");
+                        continue;
+                    }
+                    debug!("control client connected");
+                    self.clients.push(Client {
+                        stream,
+                        buffer: Vec::new(),
+                        subscribed: false,
+                    });
+                }
+                Err(e) if e.kind() == ErrorKind::WouldBlock => break,
+                Err(e) => {
+                    warn!("failed to accept control client: err={e}");
+                    break;
+                }
+            }
+        }
+
+        self.clients.retain_mut(|client| {
+            let mut chunk = [0u8; 4096];
+            let open = loop {
+                match client.stream.read(&mut chunk) {
+                    Ok(0) => break false,
+                    Ok(n) => {
+                        client.buffer.extend_from_slice(&chunk[..n]);
+                        // The rest is read once the complete lines in the buffer are handled.
+                        if client.buffer.len() > MAX_LINE {
+                            break true;
+                        }
+                    }
+                    Err(e) if e.kind() == ErrorKind::WouldBlock => break true,
+                    Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+                    Err(e) => {
+                        debug!("control client read failed: err={e}");
+                        break false;
+                    }
+                }
+            };
+
+            while let Some(end) = client.buffer.iter().position(|&x| x == b'\n') {
+                let line = client.buffer.drain(..=end).collect::<Vec<_>>();
+                let line = line.trim_ascii();
+                if line.is_empty() {
+                    continue;
+                }
+                let response = match serde_json::from_slice::<Request>(line) {
+                    Ok(Request::Subscribe) => {
+                        debug!("control client subscribed to events");
+                        client.subscribed = true;
+                        Response::ok()
+                    }
+                    Ok(request) => {
+                        debug!("control request: {request:?}");
+                        handler(request)
+                    }
+                    Err(e) => Response::error(format!("invalid request: {e}")),
+                };
+                let mut line = serde_json::to_vec(&response).unwrap_or_default();
+                line.push(b'\n');
+                if let Err(e) = client.reply(&line) {
+                    debug!("control client write failed: err={e}");
+                    return false;
+                }
+            }
+            if client.buffer.len() > MAX_LINE {
+                warn!(
+                    "disconnecting control client, request exceeds {MAX_LINE} bytes without a newline"
+                );
+                return false;
+            }
+            open
+        });
+    }
+
+    /// Sends `events` to every subscribed client. Subscribers that can't keep up are disconnected.
+    pub fn publish(&mut self, events: impl IntoIterator<Item = ClientEvent>) {
+        let mut lines = Vec::new();
+        for event in events {
+            debug!("publishing event: {event:?}");
+            if let Ok(line) = serde_json::to_vec(&event) {
+                lines.extend_from_slice(&line);
+                lines.push(b'\n');
+            }
+        }
+        if lines.is_empty() {
+            return;
+        }
+        self.clients.retain_mut(|client| {
+            if !client.subscribed {
+                return true;
+            }
+            client
+                .stream
+                .write_all(&lines)
+                .inspect_err(|e| debug!("dropping event subscriber: err={e}"))
+                .is_ok()
+        });
+    }
+}
+
+impl Drop for Server {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reply_is_written_whole() {
+        let (stream, mut peer) = UnixStream::pair().unwrap();
+        stream.set_nonblocking(true).unwrap();
+        stream.set_write_timeout(Some(REPLY_TIMEOUT)).unwrap();
+        let mut client = Client {
+            stream,
+            buffer: Vec::new(),
+            subscribed: false,
+        };
+        // Larger than the socket buffer, so that a non-blocking write would stop halfway.
+        let line = vec![b'x'; 4 * 1024 * 1024];
+        let reader = std::thread::spawn(move || {
+            let mut received = Vec::new();
+            peer.read_to_end(&mut received).unwrap();
+            received.len()
+        });
+        client.reply(&line).unwrap();
+        drop(client);
+        assert_eq!(reader.join().unwrap(), line.len());
+    }
+}
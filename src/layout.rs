@@ -0,0 +1,243 @@
+use serde::{Deserialize, Serialize};
+use std::time::Instant;
+
+use crate::Rect;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum LayoutMode {
+    /// Thumbnails stay where they were placed or dragged.
+    #[default]
+    Manual,
+    Grid,
+    Row,
+    Column,
+}
+
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Anchor {
+    #[default]
+    TopLeft,
+    TopRight,
+    BottomLeft,
+    BottomRight,
+}
+
+/// Order in which thumbnails are laid out.
+#[derive(Debug, Clone, Default)]
+pub enum Order {
+    #[default]
+    Alphabetical,
+    /// The order in which clients were detected.
+    Launch,
+    /// Listed characters first in the given order, everyone else after them in launch order.
+    Explicit(Vec<String>),
+}
+
+impl<'de> Deserialize<'de> for Order {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct Visitor;
+
+        impl<'de> serde::de::Visitor<'de> for Visitor {
+            type Value = Order;
+
+            fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+                f.write_str("\"alphabetical\", \"launch\" or a list of character names")
+            }
+
+            fn visit_str<E: serde::de::Error>(self, v: &str) -> Result<Order, E> {
+                match v {
+                    "alphabetical" => Ok(Order::Alphabetical),
+                    "launch" => Ok(Order::Launch),
+                    _ => Err(E::unknown_variant(v, &["alphabetical", "launch"])),
+                }
+            }
+
+            fn visit_seq<A: serde::de::SeqAccess<'de>>(
+                self,
+                mut seq: A,
+            ) -> Result<Order, A::Error> {
+                let mut names = Vec::new();
+                while let Some(name) = seq.next_element()? {
+                    names.push(name);
+                }
+                Ok(Order::Explicit(names))
+            }
+        }
+
+        deserializer.deserialize_any(Visitor)
+    }
+}
+
+impl Order {
+    /// Sorts `items` given their character name and detection time.
+    pub fn sort<T>(&self, items: &mut [T], key: impl Fn(&T) -> (&str, Instant)) {
+        match self {
+            Self::Alphabetical => items.sort_by(|a, b| key(a).cmp(&key(b))),
+            Self::Launch => items.sort_by_key(|x| key(x).1),
+            Self::Explicit(names) => items.sort_by_key(|x| {
+                let (name, created) = key(x);
+                let index = names.iter().position(|x| x == name).unwrap_or(names.len());
+                (index, created)
+            }),
+        }
+    }
+}
+
+/// The `[layout]` section of the config file.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LayoutConfig {
+    pub mode: LayoutMode,
+    pub anchor: Anchor,
+    /// Number of columns in `grid` mode.
+    pub columns: u16,
+    /// Space between thumbnails.
+    pub spacing: u16,
+    /// Distance between the anchor corner and the first thumbnail.
+    pub margin: u16,
+    pub order: Order,
+}
+
+impl Default for LayoutConfig {
+    fn default() -> Self {
+        Self {
+            mode: LayoutMode::default(),
+            anchor: Anchor::default(),
+            columns: 4,
+            spacing: 10,
+            margin: 10,
+            order: Order::default(),
+        }
+    }
+}
+
+/// Computes the position of each thumbnail of the given `sizes`, in order, within `area`.
+pub fn arrange(config: &LayoutConfig, area: Rect, sizes: &[(u16, u16)]) -> Vec<(i16, i16)> {
+    let spacing = config.spacing as i32;
+    let cell_width = sizes.iter().map(|x| x.0).max().unwrap_or(0) as i32;
+    let cell_height = sizes.iter().map(|x| x.1).max().unwrap_or(0) as i32;
+    let columns = config.columns.max(1) as usize;
+
+    let mut offset = (0, 0);
+    sizes
+        .iter()
+        .enumerate()
+        .map(|(i, &(width, height))| {
+            let (dx, dy) = match config.mode {
+                LayoutMode::Manual => (0, 0),
+                LayoutMode::Grid => (
+                    (i % columns) as i32 * (cell_width + spacing),
+                    (i / columns) as i32 * (cell_height + spacing),
+                ),
+                LayoutMode::Row => {
+                    let dx = offset.0;
+                    offset.0 += width as i32 + spacing;
+                    (dx, 0)
+                }
+                LayoutMode::Column => {
+                    let dy = offset.1;
+                    offset.1 += height as i32 + spacing;
+                    (0, dy)
+                }
+            };
+
+            let margin = config.margin as i32;
+            let left = area.x as i32 + margin + dx;
+            let right = area.x as i32 + area.width as i32 - margin - dx - width as i32;
+            let top = area.y as i32 + margin + dy;
+            let bottom = area.y as i32 + area.height as i32 - margin - dy - height as i32;
+            let (x, y) = match config.anchor {
+                Anchor::TopLeft => (left, top),
+                Anchor::TopRight => (right, top),
+                Anchor::BottomLeft => (left, bottom),
+                Anchor::BottomRight => (right, bottom),
+            };
+            (x as i16, y as i16)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn config(mode: LayoutMode, anchor: Anchor) -> LayoutConfig {
+        LayoutConfig {
+            mode,
+            anchor,
+            columns: 2,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn grid_uses_largest_cell() {
+        let positions = arrange(
+            &config(LayoutMode::Grid, Anchor::TopLeft),
+            Rect::new(0, 0, 1920, 1080),
+            &[(100, 50), (80, 60), (100, 50)],
+        );
+        assert_eq!(positions, [(10, 10), (120, 10), (10, 80)]);
+    }
+
+    #[test]
+    fn row_from_bottom_right() {
+        let positions = arrange(
+            &config(LayoutMode::Row, Anchor::BottomRight),
+            Rect::new(1920, 0, 1920, 1080),
+            &[(100, 50), (200, 60)],
+        );
+        assert_eq!(positions, [(3730, 1020), (3520, 1010)]);
+    }
+
+    #[test]
+    fn column_from_top_right() {
+        let positions = arrange(
+            &config(LayoutMode::Column, Anchor::TopRight),
+            Rect::new(0, 0, 1920, 1080),
+            &[(100, 50), (200, 60)],
+        );
+        assert_eq!(positions, [(1810, 10), (1710, 70)]);
+    }
+
+    #[test]
+    fn manual_stacks_on_anchor() {
+        let positions = arrange(
+            &config(LayoutMode::Manual, Anchor::BottomLeft),
+            Rect::new(0, 0, 1920, 1080),
+            &[(100, 50), (100, 50)],
+        );
+        assert_eq!(positions, [(10, 1020), (10, 1020)]);
+    }
+
+    fn sorted(order: Order) -> Vec<&'static str> {
+        let start = Instant::now();
+        let mut items = [
+            ("Charlie", start),
+            ("Alpha", start + Duration::from_secs(2)),
+            ("Bravo", start + Duration::from_secs(1)),
+            ("Delta", start + Duration::from_secs(3)),
+        ];
+        order.sort(&mut items, |x| *x);
+        items.iter().map(|x| x.0).collect()
+    }
+
+    #[test]
+    fn sort_orders() {
+        assert_eq!(
+            sorted(Order::Alphabetical),
+            ["Alpha", "Bravo", "Charlie", "Delta"]
+        );
+        assert_eq!(
+            sorted(Order::Launch),
+            ["Charlie", "Bravo", "Alpha", "Delta"]
+        );
+        assert_eq!(
+            sorted(Order::Explicit(vec!["Delta".into(), "Alpha".into()])),
+            ["Delta", "Alpha", "Charlie", "Bravo"]
+        );
+    }
+}
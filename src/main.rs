@@ -1,239 +1,261 @@
 #![forbid(unsafe_code)]
+mod config;
+mod crop;
+mod ctl;
+mod event_loop;
+mod hooks;
+mod hotkeys;
+mod ipc;
+mod layout;
+mod monitors;
+mod snap;
+mod state;
+mod status;
+mod zoom;
+
 use anyhow::Result;
+use config::{CaptureMode, Config, FitMode, ThumbnailConfig};
+use crop::Crop;
+use event_loop::Timers;
+use hooks::{HookConfig, Hooks};
+use hotkeys::{Action, Hotkeys};
+use ipc::{ClientEvent, ClientInfo, Request, Response};
+use layout::LayoutMode;
+use monitors::Monitor;
+use state::{SavedState, SavedThumbnail};
+use status::{Status, StatusFile};
 use std::collections::HashMap;
 use std::env;
+use std::os::fd::AsFd;
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
 use tracing::{Level as TraceLevel, debug, error, info, warn};
 use tracing_subscriber::FmtSubscriber;
-use x11rb::connection::Connection;
+use x11rb::connection::{Connection, RequestConnection};
 use x11rb::protocol::Event::{self, CreateNotify, DamageNotify, DestroyNotify, PropertyNotify};
+use x11rb::protocol::composite::{self, ConnectionExt as CompositeExt, Redirect};
 use x11rb::protocol::damage::{
     ConnectionExt as DamageExt, Damage, ReportLevel as DamageReportLevel,
 };
 use x11rb::protocol::render::{
-    Color, ConnectionExt as RenderExt, CreatePictureAux, Fixed, PictOp, Pictformat, Picture,
-    Transform,
+    ConnectionExt as RenderExt, CreatePictureAux, Fixed, PictOp, PictType, Pictformat,
+    Pictforminfo, Picture, Transform,
 };
+use x11rb::protocol::xfixes::{ConnectionExt as XfixesExt, Region};
 use x11rb::protocol::xproto::*;
 use x11rb::rust_connection::RustConnection;
 use x11rb::wrapper::ConnectionExt as WrapperExt;
+use zoom::Zoom;
 
-#[derive(Debug)]
-struct Config {
-    width: u16,
-    height: u16,
-    opacity: u32,
-    border_size: u16,
-    border_color: Color,
-    text_x: i16,
-    text_y: i16,
-    text_foreground: u32,
-    text_background: u32,
-    hide_when_no_focus: bool,
-}
-
-impl Config {
-    fn parse_num<T: std::str::FromStr + TryFrom<u128>>(var: &str) -> Option<T>
-    where
-        <T as TryFrom<u128>>::Error: std::fmt::Debug,
-        <T as std::str::FromStr>::Err: std::fmt::Debug,
-    {
-        if let Ok(s) = env::var(var) {
-            let s = s.trim();
-            if let Some(hex) = s.strip_prefix("0x").or_else(|| s.strip_prefix("0X"))
-                && let Ok(n) = u128::from_str_radix(hex, 16)
-            {
-                return T::try_from(n)
-                    .inspect_err(|e| error!("failed to parse '{var}' err={e:?}"))
-                    .ok();
-            } else {
-                return s
-                    .parse::<T>()
-                    .inspect_err(|e| error!("failed to parse '{var}' err={e:?}"))
-                    .ok();
-            }
-        }
-        None
+x11rb::atom_manager! {
+    /// Atoms interned once at startup.
+    Atoms: AtomsCookie {
+        WM_NAME,
+        WM_CLASS,
+        _NET_ACTIVE_WINDOW,
+        _NET_CLIENT_LIST,
+        _NET_WM_PID,
+        _NET_WM_STATE,
+        _NET_WM_STATE_ABOVE,
+        _NET_WM_STATE_HIDDEN,
+        _NET_WM_WINDOW_OPACITY,
     }
+}
 
-    fn parse_color(var: &str) -> Option<Color> {
-        if let Some(raw) = Self::parse_num::<u32>(var) {
-            let a = ((raw >> 24) & 0xFF) as u16;
-            let r = ((raw >> 16) & 0xFF) as u16;
-            let g = ((raw >> 8) & 0xFF) as u16;
-            let b = (raw & 0xFF) as u16;
-
-            let scale = |v: u16| (v as f32 / u8::MAX as f32 * u16::MAX as f32) as u16;
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+struct Rect {
+    x: i16,
+    y: i16,
+    width: u16,
+    height: u16,
+}
 
-            Some(Color {
-                red: scale(r),
-                green: scale(g),
-                blue: scale(b),
-                alpha: scale(a),
-            })
-        } else {
-            None
+impl Rect {
+    fn new(x: i16, y: i16, width: u16, height: u16) -> Self {
+        Self {
+            x,
+            y,
+            width,
+            height,
         }
     }
 
-    fn premultiply_argb32(argb: u32) -> u32 {
-        let a = (argb >> 24) & 0xFF;
-        let r = (argb >> 16) & 0xFF;
-        let g = (argb >> 8) & 0xFF;
-        let b = argb & 0xFF;
+    /// Maps the rectangle through a scale factor, grown by a pixel so that filtered scaling picks up
+    /// its neighbours, and clipped to `width` x `height`. `None` if nothing is left of it.
+    fn scaled(&self, scale_x: f32, scale_y: f32, width: u16, height: u16) -> Option<Rect> {
+        let x1 = ((self.x as f32 * scale_x).floor() as i32 - 1).max(0);
+        let y1 = ((self.y as f32 * scale_y).floor() as i32 - 1).max(0);
+        let x2 = ((((self.x as i32 + self.width as i32) as f32) * scale_x).ceil() as i32 + 1)
+            .min(width as i32);
+        let y2 = ((((self.y as i32 + self.height as i32) as f32) * scale_y).ceil() as i32 + 1)
+            .min(height as i32);
+        (x1 < x2 && y1 < y2)
+            .then(|| Rect::new(x1 as i16, y1 as i16, (x2 - x1) as u16, (y2 - y1) as u16))
+    }
 
-        let r_p = r * a / 255;
-        let g_p = g * a / 255;
-        let b_p = b * a / 255;
+    fn center(&self) -> (i32, i32) {
+        (
+            self.x as i32 + self.width as i32 / 2,
+            self.y as i32 + self.height as i32 / 2,
+        )
+    }
 
-        (a << 24) | (r_p << 16) | (g_p << 8) | b_p
+    fn intersect(&self, other: &Rect) -> Option<Rect> {
+        let x1 = (self.x as i32).max(other.x as i32);
+        let y1 = (self.y as i32).max(other.y as i32);
+        let x2 = (self.x as i32 + self.width as i32).min(other.x as i32 + other.width as i32);
+        let y2 = (self.y as i32 + self.height as i32).min(other.y as i32 + other.height as i32);
+        (x1 < x2 && y1 < y2)
+            .then(|| Rect::new(x1 as i16, y1 as i16, (x2 - x1) as u16, (y2 - y1) as u16))
     }
 
-    fn new() -> Self {
-        Self {
-            width: Self::parse_num("WIDTH").unwrap_or(240),
-            height: Self::parse_num("HEIGHT").unwrap_or(135),
-            opacity: Self::parse_num("OPACITY").unwrap_or(0xC0000000),
-            border_size: Self::parse_num("BORDER_SIZE").unwrap_or(5),
-            border_color: Self::parse_color("BORDER_COLOR").unwrap_or(Color {
-                red: 0xFFFF,
-                green: 0,
-                blue: 0,
-                alpha: 0x7F00,
-            }),
-            text_x: Self::parse_num("TEXT_X").unwrap_or(10),
-            text_y: Self::parse_num("TEXT_Y").unwrap_or(125),
-            text_foreground: Self::premultiply_argb32(
-                Self::parse_num("TEXT_FOREGROUND").unwrap_or(0xFF_FF_FF_FF),
-            ),
-            text_background: Self::premultiply_argb32(
-                Self::parse_num("TEXT_BACKGROUND").unwrap_or(0x7F_00_00_00),
-            ),
-            hide_when_no_focus: env::var("HIDE_WHEN_NO_FOCUS")
-                .map(|x| x.parse().unwrap_or(false))
-                .unwrap_or(false),
-        }
+    fn contains(&self, x: i32, y: i32) -> bool {
+        x >= self.x as i32
+            && x < self.x as i32 + self.width as i32
+            && y >= self.y as i32
+            && y < self.y as i32 + self.height as i32
     }
 }
 
+/// Thumbnails can't be resized below this many pixels in either direction.
+const MIN_THUMBNAIL_SIZE: u16 = 32;
+/// Nor above this many, which keeps the overlay allocatable and window coordinates within `i16`.
+const MAX_THUMBNAIL_SIZE: u16 = 4096;
+
 #[derive(Debug, Default)]
 struct InputState {
     dragging: bool,
+    /// The drag started with the resize modifier held and moves a corner instead of the thumbnail.
+    resizing: bool,
     drag_start: (i16, i16),
     win_start: (i16, i16),
+    size: (u16, u16),
+    /// The corner being dragged and the opposite one, which stays in place, in root coordinates.
+    corner: (i16, i16),
+    anchor: (i16, i16),
+    snap_targets: Vec<Rect>,
+}
+
+/// Picture large sources are scaled down to first with `two_pass_downscale`.
+#[derive(Debug)]
+struct Intermediate {
+    pixmap: Pixmap,
+    picture: Picture,
+    width: u16,
+    height: u16,
 }
 
 #[derive(Debug)]
 struct Thumbnail<'a> {
     window: Window,
 
-    config: &'a Config,
+    config: ThumbnailConfig,
     border_fill: Picture,
 
     src_picture: Picture,
+    /// Format of the source window's visual, which may differ from the root window's.
+    src_format: Pictformat,
+    src_alpha: bool,
+    intermediate: Option<Intermediate>,
+    /// The redirected contents of the source in pixmap capture mode.
+    src_pixmap: Option<Pixmap>,
+    redirected: bool,
+    dst_format: Pictformat,
     dst_picture: Picture,
     overlay_gc: Gcontext,
     overlay_pixmap: Pixmap,
     overlay_picture: Picture,
+    /// Ascent of the overlay font, for placing text by its top edge.
+    font_ascent: i16,
+    zoom: Option<Zoom>,
+    crops: Vec<Crop>,
 
     character_name: String,
+    created: Instant,
+    /// `_NET_WM_PID` of the client, if it is set.
+    pid: Option<u32>,
     focused: bool,
     visible: bool,
     minimized: bool,
+    /// When the thumbnail was last repainted after damage, for the frame rate limit.
+    last_frame: Option<Instant>,
+    /// The source was damaged since the last frame and the repaint is waiting for the next one.
+    pending_damage: bool,
+
+    /// Geometry of the thumbnail window, kept up to date from ConfigureNotify.
+    rect: Rect,
+    /// Size of the source window, kept up to date from ConfigureNotify.
+    src_size: (u16, u16),
 
     src: Window,
+    /// The source window was destroyed, and with it the damage object and pictures on it.
+    src_destroyed: bool,
     root: Window,
     damage: Damage,
+    /// Receives the damage taken off `damage` when repainting.
+    damaged_region: Region,
     input_state: InputState,
     conn: &'a RustConnection,
+    atoms: &'a Atoms,
+    formats: &'a PictFormats,
 }
 
 impl<'a> Thumbnail<'a> {
     fn new(
-        conn: &'a RustConnection,
-        screen: &Screen,
+        x11: &X11<'a>,
         character_name: String,
         src: Window,
         font: Font,
-        config: &'a Config,
+        config: ThumbnailConfig,
+        position: Option<(i16, i16)>,
+        monitors: &[Monitor],
     ) -> Result<Self> {
+        let X11 {
+            conn,
+            atoms,
+            formats,
+            screen,
+        } = *x11;
         let src_geom = conn.get_geometry(src)?.reply()?;
-        let x = src_geom.x + (src_geom.width - config.width) as i16 / 2;
-        let y = src_geom.y + (src_geom.height - config.height) as i16 / 2;
+        let config = Self::auto_size(config, (src_geom.width, src_geom.height));
+        // Per-character sizes can make the thumbnail larger than its client, so that centering it
+        // on the client can push it off the monitor.
+        let (x, y) = position.unwrap_or_else(|| {
+            let x = src_geom.x as i32 + (src_geom.width as i32 - config.width as i32) / 2;
+            let y = src_geom.y as i32 + (src_geom.height as i32 - config.height as i32) / 2;
+            let src_rect = Rect::new(src_geom.x, src_geom.y, src_geom.width, src_geom.height);
+            match monitors::containing(monitors, src_rect, None) {
+                Some(monitor) => monitors::clamp(x, y, config.width, config.height, monitor.rect),
+                None => (x as i16, y as i16),
+            }
+        });
 
-        let window = conn.generate_id()?;
-        conn.create_window(
-            screen.root_depth,
-            window,
+        let rect = Rect::new(x, y, config.width, config.height);
+        let window = create_preview_window(
+            conn,
+            atoms,
             screen.root,
-            x,
-            y,
-            config.width,
-            config.height,
-            0,
-            WindowClass::INPUT_OUTPUT,
-            screen.root_visual,
-            &CreateWindowAux::new().override_redirect(1).event_mask(
-                EventMask::SUBSTRUCTURE_NOTIFY
-                    | EventMask::BUTTON_PRESS
-                    | EventMask::BUTTON_RELEASE
-                    | EventMask::POINTER_MOTION,
-            ),
-        )?;
-
-        let opacity_atom = conn
-            .intern_atom(false, b"_NET_WM_WINDOW_OPACITY")?
-            .reply()?
-            .atom;
-        conn.change_property32(
-            PropMode::REPLACE,
-            window,
-            opacity_atom,
-            AtomEnum::CARDINAL,
-            &[config.opacity],
-        )?;
-
-        let wm_class = conn.intern_atom(false, b"WM_CLASS")?.reply()?.atom;
-        conn.change_property8(
-            PropMode::REPLACE,
-            window,
-            wm_class,
-            AtomEnum::STRING,
-            b"eve-l-preview\0eve-l-preview\0",
-        )?;
-
-        let net_wm_state = conn.intern_atom(false, b"_NET_WM_STATE")?.reply()?.atom;
-        let above_atom = conn
-            .intern_atom(false, b"_NET_WM_STATE_ABOVE")?
-            .reply()?
-            .atom;
-        conn.change_property32(
-            PropMode::REPLACE,
-            window,
-            net_wm_state,
-            AtomEnum::ATOM,
-            &[above_atom],
+            rect,
+            config.opacity,
+            EventMask::SUBSTRUCTURE_NOTIFY
+                | EventMask::BUTTON_PRESS
+                | EventMask::BUTTON_RELEASE
+                | EventMask::POINTER_MOTION
+                | EventMask::ENTER_WINDOW
+                | EventMask::LEAVE_WINDOW,
         )?;
-
         conn.map_window(window)?;
 
         let border_fill = conn.generate_id()?;
         conn.render_create_solid_fill(border_fill, config.border_color)?;
 
-        let pict_format = get_pictformat(conn, screen.root_depth, false)?;
-        let src_picture = conn.generate_id()?;
+        let dst_format = formats.for_visual(screen.root_visual)?;
+        let src_format = formats.for_visual(conn.get_window_attributes(src)?.reply()?.visual)?;
         let dst_picture = conn.generate_id()?;
-        conn.render_create_picture(src_picture, src, pict_format, &CreatePictureAux::new())?;
-        conn.render_create_picture(dst_picture, window, pict_format, &CreatePictureAux::new())?;
+        conn.render_create_picture(dst_picture, window, dst_format, &CreatePictureAux::new())?;
 
-        let overlay_pixmap = conn.generate_id()?;
-        let overlay_picture = conn.generate_id()?;
-        conn.create_pixmap(32, overlay_pixmap, screen.root, config.width, config.height)?;
-        conn.render_create_picture(
-            overlay_picture,
-            overlay_pixmap,
-            get_pictformat(conn, 32, true)?,
-            &CreatePictureAux::new(),
-        )?;
+        let (overlay_pixmap, overlay_picture) =
+            Self::create_overlay(conn, formats, screen.root, config.width, config.height)?;
 
         let overlay_gc = conn.generate_id()?;
         conn.create_gc(
@@ -244,36 +266,222 @@ impl<'a> Thumbnail<'a> {
                 .foreground(config.text_foreground)
                 .background(config.text_background),
         )?;
+        let font_ascent = conn.query_font(font)?.reply()?.font_ascent;
 
         let damage = conn.generate_id()?;
-        conn.damage_create(damage, src, DamageReportLevel::RAW_RECTANGLES)?;
+        conn.damage_create(damage, src, DamageReportLevel::NON_EMPTY)?;
+        let damaged_region = conn.generate_id()?;
+        conn.xfixes_create_region(damaged_region, &[])?;
 
         let mut _self = Self {
             window,
+            rect,
+            src_size: (src_geom.width, src_geom.height),
             config,
 
             border_fill,
-            src_picture,
+            src_picture: x11rb::NONE,
+            src_format,
+            src_alpha: formats.has_alpha(src_format),
+            intermediate: None,
+            src_pixmap: None,
+            redirected: false,
+            dst_format,
             dst_picture,
             overlay_gc,
             overlay_pixmap,
             overlay_picture,
+            font_ascent,
+            zoom: None,
+            crops: Vec::new(),
 
             character_name,
+            created: Instant::now(),
+            pid: None,
             focused: false,
             visible: true,
             minimized: false,
+            last_frame: None,
+            pending_damage: false,
 
             src,
+            src_destroyed: false,
             root: screen.root,
             damage,
+            damaged_region,
             input_state: InputState::default(),
             conn,
+            atoms,
+            formats,
         };
+        _self.bind_source()?;
         _self.update_name()?;
+        _self.create_crops()?;
         Ok(_self)
     }
 
+    fn create_overlay(
+        conn: &RustConnection,
+        formats: &PictFormats,
+        root: Window,
+        width: u16,
+        height: u16,
+    ) -> Result<(Pixmap, Picture)> {
+        let overlay_pixmap = conn.generate_id()?;
+        let overlay_picture = conn.generate_id()?;
+        conn.create_pixmap(32, overlay_pixmap, root, width, height)?;
+        conn.render_create_picture(
+            overlay_picture,
+            overlay_pixmap,
+            formats.for_depth(32, true)?,
+            &CreatePictureAux::new(),
+        )?;
+        Ok((overlay_pixmap, overlay_picture))
+    }
+
+    /// Creates `src_picture` for the configured capture mode. In pixmap mode the source is redirected
+    /// and its window pixmap named, which has to be repeated whenever the source is resized or mapped.
+    fn bind_source(&mut self) -> Result<()> {
+        let mut drawable = self.src;
+        match self.config.capture {
+            CaptureMode::Window if self.redirected => {
+                self.conn
+                    .composite_unredirect_window(self.src, Redirect::AUTOMATIC)?;
+                self.redirected = false;
+            }
+            CaptureMode::Window => (),
+            CaptureMode::Pixmap => {
+                if !self.redirected {
+                    self.conn
+                        .composite_redirect_window(self.src, Redirect::AUTOMATIC)?;
+                    self.redirected = true;
+                }
+                let attributes = self.conn.get_window_attributes(self.src)?.reply()?;
+                if attributes.map_state == MapState::VIEWABLE {
+                    drawable = self.conn.generate_id()?;
+                    self.conn.composite_name_window_pixmap(self.src, drawable)?;
+                } else if self.src_picture != x11rb::NONE {
+                    // Only viewable windows have a pixmap, keep the last one until MapNotify.
+                    return Ok(());
+                }
+            }
+        }
+
+        let picture = self.conn.generate_id()?;
+        self.conn.render_create_picture(
+            picture,
+            drawable,
+            self.src_format,
+            &CreatePictureAux::new(),
+        )?;
+        if self.src_picture != x11rb::NONE {
+            self.conn.render_free_picture(self.src_picture)?;
+        }
+        if let Some(pixmap) = self.src_pixmap.take() {
+            self.conn.free_pixmap(pixmap)?;
+        }
+        self.src_picture = picture;
+        self.src_pixmap = (drawable != self.src).then_some(drawable);
+        self.update_transform()?;
+        Ok(())
+    }
+
+    /// Derives the height from the source's aspect ratio in `auto-size` mode.
+    fn auto_size(mut config: ThumbnailConfig, src_size: (u16, u16)) -> ThumbnailConfig {
+        if config.fit == FitMode::AutoSize {
+            config.height = (config.width as u32 * src_size.1 as u32 / src_size.0.max(1) as u32)
+                .clamp(1, MAX_THUMBNAIL_SIZE as u32) as u16;
+        }
+        config
+    }
+
+    /// Switches to a new set of settings, e.g. after the client logged into a different character.
+    fn reconfigure(&mut self, config: ThumbnailConfig) -> Result<()> {
+        let config = Self::auto_size(config, self.src_size);
+        if (config.width, config.height) != (self.config.width, self.config.height) {
+            self.conn.configure_window(
+                self.window,
+                &ConfigureWindowAux::new()
+                    .width(config.width as u32)
+                    .height(config.height as u32),
+            )?;
+            let (overlay_pixmap, overlay_picture) = Self::create_overlay(
+                self.conn,
+                self.formats,
+                self.root,
+                config.width,
+                config.height,
+            )?;
+            self.conn.render_free_picture(self.overlay_picture)?;
+            self.conn.free_pixmap(self.overlay_pixmap)?;
+            self.overlay_pixmap = overlay_pixmap;
+            self.overlay_picture = overlay_picture;
+            self.rect.width = config.width;
+            self.rect.height = config.height;
+            self.place_crops()?;
+        }
+
+        if config.opacity != self.config.opacity {
+            self.conn.change_property32(
+                PropMode::REPLACE,
+                self.window,
+                self.atoms._NET_WM_WINDOW_OPACITY,
+                AtomEnum::CARDINAL,
+                &[config.opacity],
+            )?;
+            for crop in &self.crops {
+                self.conn.change_property32(
+                    PropMode::REPLACE,
+                    crop.window,
+                    self.atoms._NET_WM_WINDOW_OPACITY,
+                    AtomEnum::CARDINAL,
+                    &[config.opacity],
+                )?;
+            }
+        }
+
+        self.conn.render_free_picture(self.border_fill)?;
+        self.conn
+            .render_create_solid_fill(self.border_fill, config.border_color)?;
+        self.conn.change_gc(
+            self.overlay_gc,
+            &ChangeGCAux::new()
+                .foreground(config.text_foreground)
+                .background(config.text_background),
+        )?;
+
+        let capture_changed = config.capture != self.config.capture;
+        let regions_changed = config.regions != self.config.regions;
+        self.config = config;
+        if capture_changed {
+            self.bind_source()?;
+        } else {
+            self.update_transform()?;
+        }
+        if regions_changed {
+            self.create_crops()?;
+        } else {
+            for crop in &self.crops {
+                self.label_crop(crop)?;
+            }
+        }
+        if self.minimized {
+            self.minimized()?;
+        } else {
+            self.border(self.focused)?;
+            self.update()?;
+        }
+        Ok(())
+    }
+
+    /// Changes the size of the thumbnail at runtime, keeping its other settings.
+    fn resize(&mut self, width: u16, height: u16) -> Result<()> {
+        let mut config = self.config.clone();
+        config.width = width.clamp(MIN_THUMBNAIL_SIZE, MAX_THUMBNAIL_SIZE);
+        config.height = height.clamp(MIN_THUMBNAIL_SIZE, MAX_THUMBNAIL_SIZE);
+        self.reconfigure(config)
+    }
+
     fn visibility(&mut self, visible: bool) -> Result<()> {
         if visible == self.visible {
             return Ok(());
@@ -281,27 +489,253 @@ impl<'a> Thumbnail<'a> {
         self.visible = visible;
         if visible {
             self.conn.map_window(self.window)?;
+            for crop in &self.crops {
+                self.conn.map_window(crop.window)?;
+            }
         } else {
+            self.hide_zoom()?;
             self.conn.unmap_window(self.window)?;
+            for crop in &self.crops {
+                self.conn.unmap_window(crop.window)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Sets up scaling the source down to the thumbnail size, needed whenever either of them, the
+    /// scaling settings or `src_picture` change.
+    fn update_transform(&mut self) -> Result<()> {
+        let (src_width, src_height) = (self.src_size.0.max(1), self.src_size.1.max(1));
+        let content = self.content();
+        let (width, height) = (content.width, content.height);
+        // Halfway between both sizes, so that each pass scales by the square root of the ratio.
+        let intermediate_size = (self.config.two_pass_downscale
+            && (src_width as u32 > width as u32 * 2 || src_height as u32 > height as u32 * 2))
+            .then(|| {
+                (
+                    (src_width as f32 * width as f32).sqrt() as u16,
+                    (src_height as f32 * height as f32).sqrt() as u16,
+                )
+            });
+
+        if self.intermediate.as_ref().map(|x| (x.width, x.height)) != intermediate_size {
+            if let Some(intermediate) = self.intermediate.take() {
+                self.conn.render_free_picture(intermediate.picture)?;
+                self.conn.free_pixmap(intermediate.pixmap)?;
+            }
+            if let Some((width, height)) = intermediate_size {
+                let pixmap = self.conn.generate_id()?;
+                let picture = self.conn.generate_id()?;
+                self.conn.create_pixmap(
+                    self.formats.depth(self.src_format)?,
+                    pixmap,
+                    self.root,
+                    width,
+                    height,
+                )?;
+                self.conn.render_create_picture(
+                    picture,
+                    pixmap,
+                    self.src_format,
+                    &CreatePictureAux::new(),
+                )?;
+                self.intermediate = Some(Intermediate {
+                    pixmap,
+                    picture,
+                    width,
+                    height,
+                });
+            }
+        }
+
+        self.scale_source()
+    }
+
+    /// Points the transforms of `src_picture` and the intermediate picture at the thumbnail, e.g.
+    /// after `src_picture` was borrowed with a different scale.
+    fn scale_source(&self) -> Result<()> {
+        let (src_width, src_height) = (self.src_size.0.max(1), self.src_size.1.max(1));
+        let content = self.content();
+        let (width, height) = (content.width, content.height);
+        match &self.intermediate {
+            Some(intermediate) => {
+                self.set_scaling(
+                    self.src_picture,
+                    (src_width, src_height),
+                    (intermediate.width, intermediate.height),
+                )?;
+                self.set_scaling(
+                    intermediate.picture,
+                    (intermediate.width, intermediate.height),
+                    (width, height),
+                )?;
+            }
+            None => self.set_scaling(self.src_picture, (src_width, src_height), (width, height))?,
         }
         Ok(())
     }
 
-    fn capture(&self) -> Result<()> {
-        let geom = self.conn.get_geometry(self.src)?.reply()?;
+    fn set_scaling(&self, picture: Picture, from: (u16, u16), to: (u16, u16)) -> Result<()> {
         let transform = Transform {
-            matrix11: to_fixed(geom.width as f32 / self.config.width as f32),
-            matrix22: to_fixed(geom.height as f32 / self.config.height as f32),
+            matrix11: to_fixed(from.0 as f32 / to.0 as f32),
+            matrix22: to_fixed(from.1 as f32 / to.1 as f32),
             matrix33: to_fixed(1.0),
             ..Default::default()
         };
+        self.conn.render_set_picture_transform(picture, transform)?;
+        let params = self
+            .config
+            .filter
+            .params()
+            .into_iter()
+            .map(to_fixed)
+            .collect::<Vec<_>>();
         self.conn
-            .render_set_picture_transform(self.src_picture, transform)?;
-        self.conn.render_composite(
+            .render_set_picture_filter(picture, self.config.filter.name(), &params)?;
+        Ok(())
+    }
+
+    fn source_resized(&mut self, width: u16, height: u16) -> Result<()> {
+        if (width, height) == self.src_size {
+            return Ok(());
+        }
+        self.src_size = (width, height);
+        self.hide_zoom()?;
+        if self.config.capture == CaptureMode::Pixmap {
+            self.bind_source()?;
+        } else {
+            self.update_transform()?;
+        }
+        if self.config.fit == FitMode::AutoSize {
+            self.reconfigure(self.config.clone())?;
+        } else {
+            self.update()?;
+        }
+        Ok(())
+    }
+
+    /// The whole thumbnail, in thumbnail coordinates.
+    fn area(&self) -> Rect {
+        Rect::new(0, 0, self.config.width, self.config.height)
+    }
+
+    /// Where the scaled source is placed, in thumbnail coordinates. It extends past the thumbnail
+    /// in `fill` mode and leaves bars on two sides in `fit` mode.
+    fn content(&self) -> Rect {
+        let (src_width, src_height) =
+            (self.src_size.0.max(1) as f32, self.src_size.1.max(1) as f32);
+        let (width, height) = (self.config.width as f32, self.config.height as f32);
+        let scale = match self.config.fit {
+            FitMode::Stretch | FitMode::AutoSize => return self.area(),
+            FitMode::Fit => (width / src_width).min(height / src_height),
+            FitMode::Fill => (width / src_width).max(height / src_height),
+        };
+        let content_width = (src_width * scale).round().clamp(1.0, i16::MAX as f32);
+        let content_height = (src_height * scale).round().clamp(1.0, i16::MAX as f32);
+        Rect::new(
+            ((width - content_width) / 2.0) as i16,
+            ((height - content_height) / 2.0) as i16,
+            content_width as u16,
+            content_height as u16,
+        )
+    }
+
+    /// The part of the thumbnail showing the source, which the border and text are laid out in.
+    fn bounds(&self) -> Rect {
+        self.content()
+            .intersect(&self.area())
+            .unwrap_or_else(|| self.area())
+    }
+
+    /// Prepares `area` of `picture` for the source to be composited onto it and returns the operator
+    /// to composite with. The area is filled with the letterbox color if the source doesn't cover
+    /// all of it, as told by `letterbox`, or if the source has an alpha channel: those are blended
+    /// onto the background instead of copying their premultiplied colors, which is what a
+    /// compositing manager would show.
+    fn background(&self, picture: Picture, area: Rect, letterbox: bool) -> Result<PictOp> {
+        if !self.src_alpha && !letterbox {
+            return Ok(PictOp::SRC);
+        }
+        self.conn.render_fill_rectangles(
             PictOp::SRC,
-            self.src_picture,
+            picture,
+            self.config.letterbox_color,
+            &[Rectangle {
+                x: area.x,
+                y: area.y,
+                width: area.width,
+                height: area.height,
+            }],
+        )?;
+        Ok(if self.src_alpha {
+            PictOp::OVER
+        } else {
+            PictOp::SRC
+        })
+    }
+
+    fn capture(&self, area: Rect) -> Result<()> {
+        let content = self.content();
+        let visible = area.intersect(&content);
+        let op = self.background(self.dst_picture, area, visible != Some(area))?;
+        let Some(visible) = visible else {
+            return Ok(());
+        };
+        // The same area relative to the content, which is where the transform maps the source to.
+        let (src_x, src_y) = (visible.x - content.x, visible.y - content.y);
+
+        let src_picture = match &self.intermediate {
+            Some(intermediate) => {
+                let Some(inner) = Rect::new(src_x, src_y, visible.width, visible.height).scaled(
+                    intermediate.width as f32 / content.width as f32,
+                    intermediate.height as f32 / content.height as f32,
+                    intermediate.width,
+                    intermediate.height,
+                ) else {
+                    return Ok(());
+                };
+                self.conn.render_composite(
+                    PictOp::SRC,
+                    self.src_picture,
+                    0u32,
+                    intermediate.picture,
+                    inner.x,
+                    inner.y,
+                    0,
+                    0,
+                    inner.x,
+                    inner.y,
+                    inner.width,
+                    inner.height,
+                )?;
+                intermediate.picture
+            }
+            None => self.src_picture,
+        };
+
+        self.conn.render_composite(
+            op,
+            src_picture,
             0u32,
             self.dst_picture,
+            src_x,
+            src_y,
+            0,
+            0,
+            visible.x,
+            visible.y,
+            visible.width,
+            visible.height,
+        )?;
+        Ok(())
+    }
+
+    fn border(&self, focused: bool) -> Result<()> {
+        self.conn.render_composite(
+            PictOp::CLEAR,
+            self.overlay_picture,
+            0u32,
+            self.overlay_picture,
             0,
             0,
             0,
@@ -311,11 +745,8 @@ impl<'a> Thumbnail<'a> {
             self.config.width,
             self.config.height,
         )?;
-        Ok(())
-    }
-
-    fn border(&self, focused: bool) -> Result<()> {
         if focused {
+            let bounds = self.bounds();
             self.conn.render_composite(
                 PictOp::SRC,
                 self.border_fill,
@@ -325,25 +756,10 @@ impl<'a> Thumbnail<'a> {
                 0,
                 0,
                 0,
-                0,
-                0,
-                self.config.width,
-                self.config.height,
-            )?;
-        } else {
-            self.conn.render_composite(
-                PictOp::CLEAR,
-                self.overlay_picture,
-                0u32,
-                self.overlay_picture,
-                0,
-                0,
-                0,
-                0,
-                0,
-                0,
-                self.config.width,
-                self.config.height,
+                bounds.x,
+                bounds.y,
+                bounds.width,
+                bounds.height,
             )?;
         }
         self.update_name()?;
@@ -364,11 +780,12 @@ impl<'a> Thumbnail<'a> {
                     .as_slice(),
             )?
             .reply()?;
+        let bounds = self.bounds();
         self.conn.image_text8(
             self.overlay_pixmap,
             self.overlay_gc,
-            (self.config.width as i16 - extents.overall_width as i16) / 2,
-            (self.config.height as i16 + extents.font_ascent + extents.font_descent) / 2,
+            bounds.x + (bounds.width as i16 - extents.overall_width as i16) / 2,
+            bounds.y + (bounds.height as i16 + extents.font_ascent + extents.font_descent) / 2,
             b"MINIMIZED",
         )?;
         self.update()?;
@@ -377,6 +794,7 @@ impl<'a> Thumbnail<'a> {
     }
 
     fn update_name(&self) -> Result<()> {
+        let bounds = self.bounds();
         self.conn.render_composite(
             PictOp::CLEAR,
             self.overlay_picture,
@@ -386,58 +804,110 @@ impl<'a> Thumbnail<'a> {
             0,
             0,
             0,
-            self.config.border_size as i16,
-            self.config.border_size as i16,
-            self.config.width - self.config.border_size * 2,
-            self.config.height - self.config.border_size * 2,
+            bounds.x + self.config.border_size as i16,
+            bounds.y + self.config.border_size as i16,
+            bounds.width.saturating_sub(self.config.border_size * 2),
+            bounds.height.saturating_sub(self.config.border_size * 2),
         )?;
         self.conn.image_text8(
             self.overlay_pixmap,
             self.overlay_gc,
-            self.config.text_x,
-            self.config.text_y,
+            bounds.x + self.config.text_x,
+            bounds.y + self.config.text_y,
             self.character_name.as_bytes(),
         )?;
         Ok(())
     }
 
-    fn overlay(&self) -> Result<()> {
+    fn overlay(&self, area: Rect) -> Result<()> {
         self.conn.render_composite(
             PictOp::OVER,
             self.overlay_picture,
             0u32,
             self.dst_picture,
+            area.x,
+            area.y,
             0,
             0,
-            0,
-            0,
-            0,
-            0,
-            self.config.width,
-            self.config.height,
+            area.x,
+            area.y,
+            area.width,
+            area.height,
         )?;
         Ok(())
     }
 
     fn update(&self) -> Result<()> {
-        self.capture()?;
-        self.overlay()?;
+        self.capture(self.area())?;
+        self.overlay(self.area())?;
+        self.paint_crops(None)?;
         Ok(())
     }
 
-    fn focus(&self) -> Result<(), x11rb::errors::ReplyError> {
-        let net_active = self
+    /// Takes the damage accumulated on the source since the last frame and recomposites only the
+    /// parts of the thumbnail it covers. Returns the damaged parts of the source.
+    fn repair(&self) -> Result<Vec<Rect>> {
+        self.conn
+            .damage_subtract(self.damage, x11rb::NONE, self.damaged_region)?;
+        let damaged = self
             .conn
-            .intern_atom(false, b"_NET_ACTIVE_WINDOW")?
-            .reply()?
-            .atom;
+            .xfixes_fetch_region(self.damaged_region)?
+            .reply()?;
+        let content = self.content();
+        let scale_x = content.width as f32 / self.src_size.0.max(1) as f32;
+        let scale_y = content.height as f32 / self.src_size.1.max(1) as f32;
+        let damaged = damaged
+            .rectangles
+            .into_iter()
+            .map(|x| Rect::new(x.x, x.y, x.width, x.height))
+            .collect::<Vec<_>>();
+        for rect in &damaged {
+            let Some(area) = rect
+                .scaled(scale_x, scale_y, content.width, content.height)
+                .and_then(|x| {
+                    Rect::new(x.x + content.x, x.y + content.y, x.width, x.height)
+                        .intersect(&self.area())
+                })
+            else {
+                continue;
+            };
+            self.capture(area)?;
+            self.overlay(area)?;
+        }
+        Ok(damaged)
+    }
 
+    /// Repaints after the source was damaged, unless the last frame was less than `interval` ago.
+    /// In that case the repaint is deferred and the time it is due at is returned.
+    fn damaged(&mut self, interval: Duration) -> Result<Option<Instant>> {
+        if let Some(due) = self
+            .last_frame
+            .map(|x| x + interval)
+            .filter(|&x| x > Instant::now())
+        {
+            self.pending_damage = true;
+            return Ok(Some(due));
+        }
+        self.frame()?;
+        Ok(None)
+    }
+
+    fn frame(&mut self) -> Result<()> {
+        let damaged = self.repair()?;
+        self.paint_crops(Some(&damaged))?;
+        self.update_zoom()?;
+        self.last_frame = Some(Instant::now());
+        self.pending_damage = false;
+        Ok(())
+    }
+
+    fn focus(&self) -> Result<(), x11rb::errors::ReplyError> {
         let ev = ClientMessageEvent {
             response_type: CLIENT_MESSAGE_EVENT,
             format: 32,
             sequence: 0,
             window: self.src,
-            type_: net_active,
+            type_: self.atoms._NET_ACTIVE_WINDOW,
             data: [2, 0, 0, 0, 0].into(),
         };
 
@@ -452,41 +922,52 @@ impl<'a> Thumbnail<'a> {
         Ok(())
     }
 
-    fn reposition(&self, x: i16, y: i16) -> Result<()> {
+    fn reposition(&mut self, x: i16, y: i16) -> Result<()> {
         self.conn.configure_window(
             self.window,
             &ConfigureWindowAux::new().x(x as i32).y(y as i32),
         )?;
+        self.rect.x = x;
+        self.rect.y = y;
+        self.place_crops()?;
         self.conn.flush()?;
         Ok(())
     }
 
     fn is_hovered(&self, x: i16, y: i16) -> bool {
-        if let Ok(req) = self
-            .conn
-            .get_geometry(self.window)
-            .inspect_err(|x| error!("encountered error in is_hovered. err={x:#?}"))
-            && let Ok(geom) = req.reply()
-        {
-            return x >= geom.x
-                && x <= geom.x + geom.width as i16
-                && y >= geom.y
-                && y <= geom.y + geom.height as i16;
-        }
-        false
+        self.rect.contains(x as i32, y as i32)
     }
 }
 
 impl Drop for Thumbnail<'_> {
     fn drop(&mut self) {
         if let Err(e) = (|| {
-            self.conn.damage_destroy(self.damage)?;
+            if !self.src_destroyed {
+                self.conn.damage_destroy(self.damage)?;
+            }
+            self.conn.xfixes_destroy_region(self.damaged_region)?;
             self.conn.free_gc(self.overlay_gc)?;
             self.conn.render_free_picture(self.overlay_picture)?;
-            self.conn.render_free_picture(self.src_picture)?;
+            // A picture of the window pixmap outlives the window, one of the window itself doesn't.
+            if !self.src_destroyed || self.src_pixmap.is_some() {
+                self.conn.render_free_picture(self.src_picture)?;
+            }
+            if let Some(pixmap) = self.src_pixmap {
+                self.conn.free_pixmap(pixmap)?;
+            }
+            if let Some(intermediate) = &self.intermediate {
+                self.conn.render_free_picture(intermediate.picture)?;
+                self.conn.free_pixmap(intermediate.pixmap)?;
+            }
+            if self.redirected && !self.src_destroyed {
+                self.conn
+                    .composite_unredirect_window(self.src, Redirect::AUTOMATIC)?;
+            }
             self.conn.render_free_picture(self.dst_picture)?;
             self.conn.render_free_picture(self.border_fill)?;
             self.conn.free_pixmap(self.overlay_pixmap)?;
+            self.hide_zoom()?;
+            self.destroy_crops()?;
             self.conn.destroy_window(self.window)?;
             self.conn.flush()?;
             Ok::<(), anyhow::Error>(())
@@ -496,44 +977,155 @@ impl Drop for Thumbnail<'_> {
     }
 }
 
+/// `$XDG_RUNTIME_DIR`, where the control socket and the status file live.
+fn runtime_dir() -> Option<PathBuf> {
+    env::var_os("XDG_RUNTIME_DIR")
+        .filter(|x| !x.is_empty())
+        .map(PathBuf::from)
+}
+
 fn to_fixed(v: f32) -> Fixed {
     (v * (1 << 16) as f32).round() as Fixed
 }
 
-#[tracing::instrument]
-fn get_pictformat(conn: &RustConnection, depth: u8, alpha: bool) -> Result<Pictformat> {
-    if let Some(format) = conn
-        .render_query_pict_formats()?
-        .reply()?
-        .formats
-        .iter()
-        .find(|format| {
+/// Picture formats supported by the server, queried once at startup.
+#[derive(Debug)]
+struct PictFormats {
+    formats: Vec<Pictforminfo>,
+    visuals: HashMap<Visualid, Pictformat>,
+}
+
+impl PictFormats {
+    fn query(conn: &RustConnection) -> Result<Self> {
+        let reply = conn.render_query_pict_formats()?.reply()?;
+        for format in &reply.formats {
             debug!(
                 "discovered Pictformat: {}, {}",
                 format.depth, format.direct.alpha_mask
             );
-            format.depth == depth
-                && if alpha {
-                    format.direct.alpha_mask != 0
-                } else {
-                    format.direct.alpha_mask == 0
-                }
+        }
+        let visuals = reply
+            .screens
+            .iter()
+            .flat_map(|x| &x.depths)
+            .flat_map(|x| &x.visuals)
+            .map(|x| (x.visual, x.format))
+            .collect();
+        Ok(Self {
+            formats: reply.formats,
+            visuals,
         })
+    }
+
+    /// A direct format of `depth`, with or without an alpha channel.
+    fn for_depth(&self, depth: u8, alpha: bool) -> Result<Pictformat> {
+        self.formats
+            .iter()
+            .find(|format| {
+                format.type_ == PictType::DIRECT
+                    && format.depth == depth
+                    && (format.direct.alpha_mask != 0) == alpha
+            })
+            .map(|format| format.id)
+            .ok_or_else(|| anyhow::anyhow!("could not find suitable Pictformat: depth={depth}"))
+    }
+
+    /// The format of windows using `visual`.
+    fn for_visual(&self, visual: Visualid) -> Result<Pictformat> {
+        self.visuals
+            .get(&visual)
+            .copied()
+            .ok_or_else(|| anyhow::anyhow!("no Pictformat for visual={visual:#x}"))
+    }
+
+    fn depth(&self, format: Pictformat) -> Result<u8> {
+        self.formats
+            .iter()
+            .find(|x| x.id == format)
+            .map(|x| x.depth)
+            .ok_or_else(|| anyhow::anyhow!("unknown Pictformat={format:#x}"))
+    }
+
+    fn has_alpha(&self, format: Pictformat) -> bool {
+        self.formats
+            .iter()
+            .any(|x| x.id == format && x.direct.alpha_mask != 0)
+    }
+}
+
+/// Creates an override-redirect window on `root` for a preview of a client: a thumbnail, one of
+/// its regions or the zoom preview. The caller maps it.
+fn create_preview_window(
+    conn: &RustConnection,
+    atoms: &Atoms,
+    root: Window,
+    rect: Rect,
+    opacity: u32,
+    event_mask: EventMask,
+) -> Result<Window> {
+    let window = conn.generate_id()?;
+    conn.create_window(
+        x11rb::COPY_DEPTH_FROM_PARENT,
+        window,
+        root,
+        rect.x,
+        rect.y,
+        rect.width,
+        rect.height,
+        0,
+        WindowClass::INPUT_OUTPUT,
+        x11rb::COPY_FROM_PARENT,
+        &CreateWindowAux::new()
+            .override_redirect(1)
+            .event_mask(event_mask),
+    )?;
+
+    conn.change_property32(
+        PropMode::REPLACE,
+        window,
+        atoms._NET_WM_WINDOW_OPACITY,
+        AtomEnum::CARDINAL,
+        &[opacity],
+    )?;
+
+    conn.change_property8(
+        PropMode::REPLACE,
+        window,
+        atoms.WM_CLASS,
+        AtomEnum::STRING,
+        b"eve-l-preview\0eve-l-preview\0",
+    )?;
+
+    conn.change_property32(
+        PropMode::REPLACE,
+        window,
+        atoms._NET_WM_STATE,
+        AtomEnum::ATOM,
+        &[atoms._NET_WM_STATE_ABOVE],
+    )?;
+    Ok(window)
+}
+
+/// Pixmap capture redirects the clients with Composite, which not every X server supports.
+fn check_composite(conn: &RustConnection, config: &Config) -> Result<()> {
+    if config.thumbnail.capture != CaptureMode::Pixmap {
+        return Ok(());
+    }
+    if conn
+        .extension_information(composite::X11_EXTENSION_NAME)?
+        .is_none()
     {
-        debug!(
-            "using Pictformat: {}, {}",
-            format.depth, format.direct.alpha_mask
+        anyhow::bail!(
+            "capture = \"pixmap\" needs the Composite extension, which the X server doesn't support"
         );
-        Ok(format.id)
-    } else {
-        anyhow::bail!("could not find suitable Pictformat")
     }
+    conn.composite_query_version(0, 4)?.reply()?;
+    Ok(())
 }
 
-fn is_window_eve(conn: &RustConnection, window: Window) -> Result<Option<String>> {
-    let wm_name = conn.intern_atom(false, b"WM_NAME")?.reply()?.atom;
+fn is_window_eve(conn: &RustConnection, atoms: &Atoms, window: Window) -> Result<Option<String>> {
     let name_prop = conn
-        .get_property(false, window, wm_name, AtomEnum::STRING, 0, 1024)?
+        .get_property(false, window, atoms.WM_NAME, AtomEnum::STRING, 0, 1024)?
         .reply()?;
     let title = String::from_utf8_lossy(&name_prop.value).into_owned();
     Ok(if let Some(name) = title.strip_prefix("EVE - ") {
@@ -545,19 +1137,85 @@ fn is_window_eve(conn: &RustConnection, window: Window) -> Result<Option<String>
     })
 }
 
+/// The settings of `character_name`, with the size its thumbnail was last resized to and the
+/// positions its region windows were dragged to.
+fn thumbnail_config(config: &Config, saved: &SavedState, character_name: &str) -> ThumbnailConfig {
+    let mut thumbnail_config = config.for_character(character_name);
+    if let Some(SavedThumbnail {
+        width: Some(width),
+        height: Some(height),
+        ..
+    }) = saved.get(character_name)
+    {
+        thumbnail_config.width = width;
+        thumbnail_config.height = height;
+    }
+    for region in &mut thumbnail_config.regions {
+        if let Some(position) = saved.get_region(character_name, &region.name) {
+            region.position = Some((position.x, position.y));
+        }
+    }
+    thumbnail_config
+}
+
+/// Where a window dragged according to `input` goes with the pointer at `event`, snapped to
+/// `input.snap_targets` and the monitor edges unless the bypass modifier is held.
+fn drag_position(
+    config: &Config,
+    monitors: &[Monitor],
+    input: &InputState,
+    event: &MotionNotifyEvent,
+) -> (i16, i16) {
+    let new_x = input.win_start.0 + event.root_x - input.drag_start.0;
+    let new_y = input.win_start.1 + event.root_y - input.drag_start.1;
+    if config.snap_threshold == 0 || config.snap_bypass_modifier.held(event.state) {
+        return (new_x, new_y);
+    }
+    snap::snap_position(
+        Rect::new(new_x, new_y, input.size.0, input.size.1),
+        &input.snap_targets,
+        &monitors.iter().map(|x| x.rect).collect::<Vec<_>>(),
+        config.snap_threshold,
+        config.snap_gap,
+    )
+}
+
+/// Position restored from the state file, moved onto a connected monitor if its output is gone.
+fn saved_position(
+    config: &Config,
+    saved: &SavedState,
+    monitors: &[Monitor],
+    character_name: &str,
+    thumbnail_config: &ThumbnailConfig,
+) -> Option<(i16, i16)> {
+    let position = saved.get(character_name)?;
+    let rect = Rect::new(
+        position.x,
+        position.y,
+        thumbnail_config.width,
+        thumbnail_config.height,
+    );
+    Some(
+        monitors::relocate(rect, &[], monitors, config.output.as_deref())
+            .unwrap_or((position.x, position.y)),
+    )
+}
+
 fn check_and_create_window<'a>(
-    conn: &'a RustConnection,
-    screen: &Screen,
-    config: &'a Config,
+    x11: &X11<'a>,
+    config: &Config,
+    saved: &SavedState,
+    monitors: &[Monitor],
     window: Window,
 ) -> Result<Option<Thumbnail<'a>>> {
-    let pid_atom = conn.intern_atom(false, b"_NET_WM_PID")?.reply()?.atom;
+    let X11 { conn, atoms, .. } = *x11;
+    let mut pid = None;
     if let Ok(prop) = conn
-        .get_property(false, window, pid_atom, AtomEnum::CARDINAL, 0, 1)?
+        .get_property(false, window, atoms._NET_WM_PID, AtomEnum::CARDINAL, 0, 1)?
         .reply()
     {
         if !prop.value.is_empty() {
-            let pid = u32::from_ne_bytes(prop.value[0..4].try_into()?);
+            let pid = *pid.insert(u32::from_ne_bytes(prop.value[0..4].try_into()?));
             if !std::fs::read_link(format!("/proc/{pid}/exe"))
                 .map(|x| {
                     x.to_string_lossy().contains("wine64-preloader")
@@ -580,16 +1238,36 @@ fn check_and_create_window<'a>(
         &ChangeWindowAttributesAux::new().event_mask(EventMask::PROPERTY_CHANGE),
     )?;
 
-    if let Some(character_name) = is_window_eve(conn, window)? {
+    if let Some(character_name) = is_window_eve(conn, atoms, window)? {
         conn.change_window_attributes(
             window,
-            &ChangeWindowAttributesAux::new()
-                .event_mask(EventMask::PROPERTY_CHANGE | EventMask::FOCUS_CHANGE),
+            &ChangeWindowAttributesAux::new().event_mask(
+                EventMask::PROPERTY_CHANGE | EventMask::FOCUS_CHANGE | EventMask::STRUCTURE_NOTIFY,
+            ),
         )?;
 
         let font = conn.generate_id()?;
         conn.open_font(font, b"fixed")?;
-        let thumbnail = Thumbnail::new(conn, screen, character_name, window, font, config)?;
+        let thumbnail_config = thumbnail_config(config, saved, &character_name);
+        let position = saved_position(config, saved, monitors, &character_name, &thumbnail_config)
+            .or_else(|| {
+                let output = config.output.as_deref()?;
+                let monitor = monitors::target(monitors, Some(output))?.rect;
+                Some((
+                    monitor.x + (monitor.width as i16 - thumbnail_config.width as i16) / 2,
+                    monitor.y + (monitor.height as i16 - thumbnail_config.height as i16) / 2,
+                ))
+            });
+        let mut thumbnail = Thumbnail::new(
+            x11,
+            character_name,
+            window,
+            font,
+            thumbnail_config,
+            position,
+            monitors,
+        )?;
+        thumbnail.pid = pid;
         conn.close_font(font)?;
         info!("constructed Thumbnail for eve window: window={window}");
         Ok(Some(thumbnail))
@@ -599,16 +1277,22 @@ fn check_and_create_window<'a>(
 }
 
 fn get_eves<'a>(
-    conn: &'a RustConnection,
-    screen: &Screen,
-    config: &'a Config,
+    x11: &X11<'a>,
+    config: &Config,
+    saved: &SavedState,
+    monitors: &[Monitor],
 ) -> Result<HashMap<Window, Thumbnail<'a>>> {
-    let net_client_list = conn.intern_atom(false, b"_NET_CLIENT_LIST")?.reply()?.atom;
+    let X11 {
+        conn,
+        atoms,
+        screen,
+        ..
+    } = *x11;
     let prop = conn
         .get_property(
             false,
             screen.root,
-            net_client_list,
+            atoms._NET_CLIENT_LIST,
             AtomEnum::WINDOW,
             0,
             u32::MAX,
@@ -618,7 +1302,7 @@ fn get_eves<'a>(
 
     let mut eves = HashMap::new();
     for w in windows {
-        if let Some(eve) = check_and_create_window(conn, screen, config, w)? {
+        if let Some(eve) = check_and_create_window(x11, config, saved, monitors, w)? {
             eves.insert(w, eve);
         }
     }
@@ -626,63 +1310,273 @@ fn get_eves<'a>(
     Ok(eves)
 }
 
-fn handle_event<'a>(
+/// Arranges all thumbnails according to the `[layout]` config, unless it is set to `manual`.
+fn apply_layout(
+    config: &Config,
+    monitors: &[Monitor],
+    eves: &mut HashMap<Window, Thumbnail>,
+) -> Result<()> {
+    if config.layout.mode == LayoutMode::Manual {
+        return Ok(());
+    }
+    let Some(monitor) = monitors::target(monitors, config.output.as_deref()) else {
+        return Ok(());
+    };
+
+    let mut thumbnails = eves.values_mut().collect::<Vec<_>>();
+    config
+        .layout
+        .order
+        .sort(&mut thumbnails, |x| (x.character_name.as_str(), x.created));
+    let sizes = thumbnails
+        .iter()
+        .map(|x| (x.config.width, x.config.height))
+        .collect::<Vec<_>>();
+    for (thumbnail, (x, y)) in
+        thumbnails
+            .iter_mut()
+            .zip(layout::arrange(&config.layout, monitor.rect, &sizes))
+    {
+        thumbnail.reposition(x, y)?;
+    }
+    Ok(())
+}
+
+/// Focuses the next or previous client in the configured hotkey order.
+fn cycle_focus(config: &Config, eves: &HashMap<Window, Thumbnail>, forward: bool) -> Result<()> {
+    let mut thumbnails = eves.values().collect::<Vec<_>>();
+    config
+        .hotkeys
+        .order
+        .sort(&mut thumbnails, |x| (x.character_name.as_str(), x.created));
+    let Some(last) = thumbnails.len().checked_sub(1) else {
+        return Ok(());
+    };
+    let next = match thumbnails.iter().position(|x| x.focused) {
+        Some(i) if forward => (i + 1) % thumbnails.len(),
+        Some(i) => i.checked_sub(1).unwrap_or(last),
+        None if forward => 0,
+        None => last,
+    };
+    thumbnails[next].focus()?;
+    Ok(())
+}
+
+/// How often running hook commands are checked for having exited.
+const HOOK_REAP_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Work scheduled on the event loop's timers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Timer {
+    /// A deferred repaint of the thumbnail of this source window.
+    Frame(Window),
+    /// Shows the zoom preview of the thumbnail of this source window once the pointer rested on it.
+    Zoom(Window),
+    /// Reaps hook commands that exited while nothing else woke the main loop up.
+    Reap,
+}
+
+/// The connection and what was queried from the X server at startup.
+#[derive(Clone, Copy)]
+struct X11<'a> {
     conn: &'a RustConnection,
-    screen: &Screen,
-    config: &'a Config,
+    atoms: &'a Atoms,
+    formats: &'a PictFormats,
+    screen: &'a Screen,
+}
+
+/// Long-lived state shared by the event handlers.
+struct Context<'a> {
+    x11: X11<'a>,
+    config_path: Option<PathBuf>,
+    config: Config,
+    saved: SavedState,
+    monitors: Vec<Monitor>,
+    hotkeys: Hotkeys,
+    /// Client state changes since the last iteration of the main loop, published to subscribers.
+    events: Vec<ClientEvent>,
+    hooks: Hooks,
+    timers: Timers<Timer>,
+}
+
+/// Queues `event` for control socket subscribers and spawns its hook command.
+fn emit(
+    events: &mut Vec<ClientEvent>,
+    hooks: &mut Hooks,
+    config: &HookConfig,
+    pid: Option<u32>,
+    event: ClientEvent,
+) {
+    hooks.run(config, &event, pid);
+    events.push(event);
+}
+
+fn handle_event<'a>(
+    ctx: &mut Context<'a>,
     eves: &mut HashMap<Window, Thumbnail<'a>>,
     event: Event,
 ) -> Result<()> {
+    let x11 = ctx.x11;
+    let X11 {
+        conn,
+        atoms,
+        screen,
+        ..
+    } = x11;
+    let Context {
+        config,
+        saved,
+        monitors,
+        hotkeys,
+        events,
+        hooks,
+        timers,
+        ..
+    } = ctx;
     match event {
         DamageNotify(event) => {
             if let Some(thumbnail) = eves
-                .values()
+                .values_mut()
                 .find(|thumbnail| thumbnail.damage == event.damage)
             {
-                thumbnail.update()?; // TODO: add fps limiter?
-                conn.damage_subtract(event.damage, 0u32, 0u32)?;
+                if let Some(due) = thumbnail.damaged(config.frame_interval(thumbnail.focused))? {
+                    timers.schedule(due, Timer::Frame(thumbnail.src));
+                }
                 conn.flush()?;
             }
         }
         CreateNotify(event) => {
-            if let Some(thumbnail) = check_and_create_window(conn, screen, config, event.window)? {
+            if let Some(thumbnail) =
+                check_and_create_window(&x11, config, saved, monitors, event.window)?
+            {
+                emit(
+                    events,
+                    hooks,
+                    &config.hooks,
+                    thumbnail.pid,
+                    ClientEvent::Added {
+                        window: event.window,
+                        character: thumbnail.character_name.clone(),
+                    },
+                );
                 eves.insert(event.window, thumbnail);
+                apply_layout(config, monitors, eves)?;
             }
         }
         DestroyNotify(event) => {
-            eves.remove(&event.window);
+            if let Some(mut thumbnail) = eves.remove(&event.window) {
+                thumbnail.src_destroyed = true;
+                emit(
+                    events,
+                    hooks,
+                    &config.hooks,
+                    thumbnail.pid,
+                    ClientEvent::Removed {
+                        window: event.window,
+                        character: thumbnail.character_name.clone(),
+                    },
+                );
+                apply_layout(config, monitors, eves)?;
+            }
         }
         PropertyNotify(event) => {
-            let wm_name = conn.intern_atom(false, b"WM_NAME")?.reply()?.atom;
-            let net_wm_state = conn.intern_atom(false, b"_NET_WM_STATE")?.reply()?.atom;
-            let net_wm_state_hidden = conn
-                .intern_atom(false, b"_NET_WM_STATE_HIDDEN")?
-                .reply()?
-                .atom;
-            if event.atom == wm_name
+            if event.atom == atoms.WM_NAME
                 && let Some(thumbnail) = eves.get_mut(&event.window)
-                && let Some(character_name) = is_window_eve(conn, event.window)?
+                && let Some(character_name) = is_window_eve(conn, atoms, event.window)?
             {
-                thumbnail.character_name = character_name;
-                thumbnail.update_name()?;
-            } else if event.atom == wm_name
+                // Wine sets the same title again now and then.
+                if character_name == thumbnail.character_name {
+                    return Ok(());
+                }
+                let thumbnail_config = thumbnail_config(config, saved, &character_name);
+                if let Some((x, y)) =
+                    saved_position(config, saved, monitors, &character_name, &thumbnail_config)
+                {
+                    thumbnail.reposition(x, y)?;
+                }
+                emit(
+                    events,
+                    hooks,
+                    &config.hooks,
+                    thumbnail.pid,
+                    ClientEvent::Renamed {
+                        window: event.window,
+                        character: character_name.clone(),
+                        previous: std::mem::replace(&mut thumbnail.character_name, character_name),
+                    },
+                );
+                thumbnail.reconfigure(thumbnail_config)?;
+                apply_layout(config, monitors, eves)?;
+            } else if event.atom == atoms.WM_NAME
                 && let Some(thumbnail) =
-                    check_and_create_window(conn, screen, config, event.window)?
+                    check_and_create_window(&x11, config, saved, monitors, event.window)?
             {
+                emit(
+                    events,
+                    hooks,
+                    &config.hooks,
+                    thumbnail.pid,
+                    ClientEvent::Added {
+                        window: event.window,
+                        character: thumbnail.character_name.clone(),
+                    },
+                );
                 eves.insert(event.window, thumbnail);
-            } else if event.atom == net_wm_state
+                apply_layout(config, monitors, eves)?;
+            } else if event.atom == atoms._NET_WM_STATE
                 && let Some(thumbnail) = eves.get_mut(&event.window)
                 && let Some(state) = conn
                     .get_property(false, event.window, event.atom, AtomEnum::ATOM, 0, 1024)?
                     .reply()?
                     .value32()
-                && state.collect::<Vec<_>>().contains(&net_wm_state_hidden)
+                && state
+                    .collect::<Vec<_>>()
+                    .contains(&atoms._NET_WM_STATE_HIDDEN)
             {
+                if !thumbnail.minimized {
+                    emit(
+                        events,
+                        hooks,
+                        &config.hooks,
+                        thumbnail.pid,
+                        ClientEvent::Minimized {
+                            window: event.window,
+                            character: thumbnail.character_name.clone(),
+                        },
+                    );
+                }
                 thumbnail.minimized()?;
             }
         }
+        // Hotkey grabs take the keyboard focus away from the client and back, skip those events.
         Event::FocusIn(event) => {
-            if let Some(thumbnail) = eves.get_mut(&event.event) {
+            if let Some(thumbnail) = eves.get_mut(&event.event)
+                && !matches!(event.mode, NotifyMode::GRAB | NotifyMode::UNGRAB)
+            {
+                if thumbnail.minimized {
+                    emit(
+                        events,
+                        hooks,
+                        &config.hooks,
+                        thumbnail.pid,
+                        ClientEvent::Restored {
+                            window: event.event,
+                            character: thumbnail.character_name.clone(),
+                        },
+                    );
+                }
+                if !thumbnail.focused {
+                    emit(
+                        events,
+                        hooks,
+                        &config.hooks,
+                        thumbnail.pid,
+                        ClientEvent::FocusIn {
+                            window: event.event,
+                            character: thumbnail.character_name.clone(),
+                        },
+                    );
+                }
                 thumbnail.minimized = false;
                 thumbnail.focused = true;
                 thumbnail.border(true)?;
@@ -694,7 +1588,21 @@ fn handle_event<'a>(
             }
         }
         Event::FocusOut(event) => {
-            if let Some(thumbnail) = eves.get_mut(&event.event) {
+            if let Some(thumbnail) = eves.get_mut(&event.event)
+                && !matches!(event.mode, NotifyMode::GRAB | NotifyMode::UNGRAB)
+            {
+                if thumbnail.focused {
+                    emit(
+                        events,
+                        hooks,
+                        &config.hooks,
+                        thumbnail.pid,
+                        ClientEvent::FocusOut {
+                            window: event.event,
+                            character: thumbnail.character_name.clone(),
+                        },
+                    );
+                }
                 thumbnail.focused = false;
                 thumbnail.border(false)?;
                 if config.hide_when_no_focus && eves.values().all(|x| !x.focused && !x.minimized) {
@@ -704,78 +1612,664 @@ fn handle_event<'a>(
                 }
             }
         }
+        Event::ConfigureNotify(event) => {
+            if event.window == screen.root {
+                update_monitors(conn, screen, config, monitors, eves)?;
+            } else if let Some(thumbnail) = eves.get_mut(&event.window) {
+                thumbnail.source_resized(event.width, event.height)?;
+            } else if let Some(thumbnail) = eves.values_mut().find(|x| x.window == event.window) {
+                thumbnail.rect = Rect::new(event.x, event.y, event.width, event.height);
+            }
+        }
+        Event::MapNotify(event) => {
+            if let Some(thumbnail) = eves.get_mut(&event.window)
+                && thumbnail.config.capture == CaptureMode::Pixmap
+            {
+                thumbnail.bind_source()?;
+                thumbnail.update()?;
+            }
+        }
+        Event::EnterNotify(event) => {
+            // Grabs ending, e.g. after a drag, re-enter the thumbnail under the pointer without it
+            // having moved there.
+            if config.zoom_width != 0
+                && event.mode == NotifyMode::NORMAL
+                && let Some(thumbnail) = eves.values().find(|x| x.window == event.event)
+                && !thumbnail.input_state.dragging
+            {
+                timers.schedule(
+                    Instant::now() + config.zoom_delay,
+                    Timer::Zoom(thumbnail.src),
+                );
+            }
+        }
+        Event::LeaveNotify(event) => {
+            if let Some(thumbnail) = eves.values_mut().find(|x| x.window == event.event) {
+                timers.cancel(&Timer::Zoom(thumbnail.src));
+                thumbnail.hide_zoom()?;
+                conn.flush()?;
+            }
+        }
         Event::ButtonPress(event) => {
-            if let Some((_, thumbnail)) = eves
-                .iter_mut()
-                .find(|(_, thumb)| thumb.visible && thumb.is_hovered(event.root_x, event.root_y))
+            if eves
+                .values()
+                .any(|thumb| thumb.crops.iter().any(|crop| crop.window == event.event))
             {
-                let geom = conn.get_geometry(thumbnail.window)?.reply()?;
-                thumbnail.input_state.drag_start = (event.root_x, event.root_y);
-                thumbnail.input_state.win_start = (geom.x, geom.y);
-                thumbnail.input_state.dragging = true;
+                let snap_targets = eves
+                    .values()
+                    .filter(|thumb| thumb.visible)
+                    .flat_map(|thumb| {
+                        std::iter::once(thumb.rect).chain(
+                            thumb
+                                .crops
+                                .iter()
+                                .filter(|crop| crop.window != event.event)
+                                .map(|crop| crop.rect),
+                        )
+                    })
+                    .collect();
+                if let Some(crop) = eves
+                    .values_mut()
+                    .flat_map(|thumb| thumb.crops.iter_mut())
+                    .find(|crop| crop.window == event.event)
+                {
+                    let rect = crop.rect;
+                    crop.input_state = InputState {
+                        dragging: true,
+                        drag_start: (event.root_x, event.root_y),
+                        win_start: (rect.x, rect.y),
+                        size: (rect.width, rect.height),
+                        snap_targets,
+                        ..Default::default()
+                    };
+                }
+            } else if let Some(window) = eves
+                .values()
+                .find(|thumb| thumb.visible && thumb.is_hovered(event.root_x, event.root_y))
+                .map(|thumb| thumb.window)
+            {
+                if let Some(thumbnail) = eves.values_mut().find(|thumb| thumb.window == window) {
+                    timers.cancel(&Timer::Zoom(thumbnail.src));
+                    thumbnail.hide_zoom()?;
+                }
+                if config.resize_modifier.held(event.state) && matches!(event.detail, 4 | 5) {
+                    if let Some(thumbnail) = eves.values_mut().find(|thumb| thumb.window == window)
+                    {
+                        let scale = if event.detail == 4 { 1.1 } else { 1.0 / 1.1 };
+                        let Rect {
+                            x,
+                            y,
+                            width,
+                            height,
+                        } = thumbnail.rect;
+                        thumbnail.resize(
+                            (width as f32 * scale).round() as u16,
+                            (height as f32 * scale).round() as u16,
+                        )?;
+                        saved.set_geometry(
+                            &thumbnail.character_name,
+                            x,
+                            y,
+                            thumbnail.config.width,
+                            thumbnail.config.height,
+                        );
+                        conn.flush()?;
+                    }
+                    apply_layout(config, monitors, eves)?;
+                    return Ok(());
+                }
+                // Attached regions of the dragged thumbnail move along with it.
+                let snap_targets = eves
+                    .values()
+                    .filter(|thumb| thumb.visible)
+                    .flat_map(|thumb| {
+                        let own = thumb.window == window;
+                        (!own).then_some(thumb.rect).into_iter().chain(
+                            thumb
+                                .crops
+                                .iter()
+                                .filter(move |crop| !(own && crop.attached))
+                                .map(|crop| crop.rect),
+                        )
+                    })
+                    .collect();
+                if let Some(thumbnail) = eves.values_mut().find(|thumb| thumb.window == window) {
+                    let rect = thumbnail.rect;
+                    thumbnail.input_state.drag_start = (event.root_x, event.root_y);
+                    thumbnail.input_state.win_start = (rect.x, rect.y);
+                    thumbnail.input_state.size = (rect.width, rect.height);
+                    thumbnail.input_state.snap_targets = snap_targets;
+                    thumbnail.input_state.dragging = true;
+                    if config.resize_modifier.held(event.state) {
+                        // Resize from the corner nearest to the pointer.
+                        let (center_x, center_y) = rect.center();
+                        let (left, right) = (rect.x, rect.x + rect.width as i16);
+                        let (top, bottom) = (rect.y, rect.y + rect.height as i16);
+                        let (corner_x, anchor_x) = if (event.root_x as i32) < center_x {
+                            (left, right)
+                        } else {
+                            (right, left)
+                        };
+                        let (corner_y, anchor_y) = if (event.root_y as i32) < center_y {
+                            (top, bottom)
+                        } else {
+                            (bottom, top)
+                        };
+                        thumbnail.input_state.resizing = true;
+                        thumbnail.input_state.corner = (corner_x, corner_y);
+                        thumbnail.input_state.anchor = (anchor_x, anchor_y);
+                    }
+                }
             }
         }
         Event::ButtonRelease(event) => {
-            if let Some((_, thumbnail)) = eves.iter_mut().find(|(_, thumb)| {
-                thumb.visible
-                    && thumb.input_state.dragging
-                    && thumb.is_hovered(event.root_x, event.root_y)
+            if let Some((thumbnail, i)) = eves.values_mut().find_map(|thumb| {
+                let i = thumb
+                    .crops
+                    .iter()
+                    .position(|crop| crop.input_state.dragging)?;
+                Some((thumb, i))
             }) {
-                if event.detail == 1
-                    && thumbnail.input_state.drag_start == (event.root_x, event.root_y)
-                {
+                let crop = &mut thumbnail.crops[i];
+                let clicked = crop.input_state.drag_start == (event.root_x, event.root_y);
+                crop.input_state = InputState::default();
+                if !clicked {
+                    crop.attached = false;
+                    saved.set_region_position(
+                        &thumbnail.character_name,
+                        &crop.name,
+                        crop.rect.x,
+                        crop.rect.y,
+                    );
+                } else if event.detail == 1 {
                     thumbnail.focus()?;
                 }
-                thumbnail.input_state.dragging = false;
+            } else if let Some(thumbnail) = eves
+                .values_mut()
+                .find(|thumb| thumb.visible && thumb.input_state.dragging)
+            {
+                let resized = thumbnail.input_state.resizing;
+                if thumbnail.input_state.drag_start == (event.root_x, event.root_y) {
+                    if event.detail == 1 {
+                        thumbnail.focus()?;
+                    }
+                } else {
+                    let Rect { x, y, .. } = thumbnail.rect;
+                    if resized {
+                        saved.set_geometry(
+                            &thumbnail.character_name,
+                            x,
+                            y,
+                            thumbnail.config.width,
+                            thumbnail.config.height,
+                        );
+                    } else {
+                        saved.set_position(&thumbnail.character_name, x, y);
+                    }
+                    emit(
+                        events,
+                        hooks,
+                        &config.hooks,
+                        thumbnail.pid,
+                        ClientEvent::Moved {
+                            window: thumbnail.src,
+                            character: thumbnail.character_name.clone(),
+                            x,
+                            y,
+                        },
+                    );
+                }
+                thumbnail.input_state = InputState::default();
+                if resized {
+                    apply_layout(config, monitors, eves)?;
+                }
             }
         }
         Event::MotionNotify(event) => {
-            if let Some((_, thumbnail)) = eves.iter_mut().find(|(_, thumb)| {
-                thumb.visible
-                    && thumb.input_state.dragging
-                    && thumb.is_hovered(event.root_x, event.root_y)
-            }) {
-                // TODO: snap to be inline with other thumbnails
-                let dx = event.root_x - thumbnail.input_state.drag_start.0;
-                let dy = event.root_y - thumbnail.input_state.drag_start.1;
-                let new_x = thumbnail.input_state.win_start.0 + dx;
-                let new_y = thumbnail.input_state.win_start.1 + dy;
-                thumbnail.reposition(new_x, new_y)?;
+            if let Some(thumbnail) = eves
+                .values_mut()
+                .find(|thumb| thumb.visible && thumb.input_state.dragging)
+            {
+                let input = &thumbnail.input_state;
+                let dx = event.root_x - input.drag_start.0;
+                let dy = event.root_y - input.drag_start.1;
+                if input.resizing {
+                    let (anchor_x, anchor_y) = input.anchor;
+                    let (corner_x, corner_y) = (input.corner.0 + dx, input.corner.1 + dy);
+                    let left = input.corner.0 < anchor_x;
+                    let top = input.corner.1 < anchor_y;
+                    let width = if left {
+                        anchor_x - corner_x
+                    } else {
+                        corner_x - anchor_x
+                    };
+                    let height = if top {
+                        anchor_y - corner_y
+                    } else {
+                        corner_y - anchor_y
+                    };
+                    thumbnail.resize(width.max(0) as u16, height.max(0) as u16)?;
+                    // The size may have been clamped or derived from the client in auto-size mode.
+                    let Rect { width, height, .. } = thumbnail.rect;
+                    thumbnail.reposition(
+                        if left {
+                            anchor_x - width as i16
+                        } else {
+                            anchor_x
+                        },
+                        if top {
+                            anchor_y - height as i16
+                        } else {
+                            anchor_y
+                        },
+                    )?;
+                    return Ok(());
+                }
+                let (x, y) = drag_position(config, monitors, input, &event);
+                thumbnail.reposition(x, y)?;
+            } else if let Some(crop) = eves
+                .values_mut()
+                .flat_map(|thumb| thumb.crops.iter_mut())
+                .find(|crop| crop.input_state.dragging)
+            {
+                let (x, y) = drag_position(config, monitors, &crop.input_state, &event);
+                crop.reposition(conn, x, y)?;
             }
         }
+        Event::RandrScreenChangeNotify(_) | Event::RandrNotify(_) => {
+            update_monitors(conn, screen, config, monitors, eves)?;
+        }
+        Event::KeyPress(event) => match hotkeys.action(&event) {
+            Some(Action::CycleForward) => cycle_focus(config, eves, true)?,
+            Some(Action::CycleBackward) => cycle_focus(config, eves, false)?,
+            Some(Action::Focus(character_name)) => {
+                if let Some(thumbnail) = eves.values().find(|x| &x.character_name == character_name)
+                {
+                    thumbnail.focus()?;
+                } else {
+                    warn!(
+                        "hotkey pressed for a character without a client: character={character_name}"
+                    );
+                }
+            }
+            None => (),
+        },
         _ => (),
     }
     Ok(())
 }
 
+/// Re-queries the monitors after a RandR change and moves thumbnails and region windows off the
+/// ones that are gone. Several events can report the same change, so nothing happens if the
+/// monitors are the same.
+fn update_monitors(
+    conn: &RustConnection,
+    screen: &Screen,
+    config: &Config,
+    monitors: &mut Vec<Monitor>,
+    eves: &mut HashMap<Window, Thumbnail>,
+) -> Result<()> {
+    let new = monitors::query(conn, screen, config.output.as_deref());
+    if new == *monitors {
+        return Ok(());
+    }
+    let old = std::mem::replace(monitors, new);
+    for thumbnail in eves.values_mut() {
+        if let Some((x, y)) =
+            monitors::relocate(thumbnail.rect, &old, monitors, config.output.as_deref())
+        {
+            info!(
+                "moving thumbnail off a disconnected monitor: window={}",
+                thumbnail.window
+            );
+            thumbnail.reposition(x, y)?;
+        }
+        // Attached regions already moved along with the thumbnail.
+        for crop in thumbnail.crops.iter_mut().filter(|x| !x.attached) {
+            if let Some((x, y)) =
+                monitors::relocate(crop.rect, &old, monitors, config.output.as_deref())
+            {
+                crop.reposition(conn, x, y)?;
+            }
+        }
+    }
+    apply_layout(config, monitors, eves)
+}
+
+fn handle_timer(
+    ctx: &mut Context,
+    eves: &mut HashMap<Window, Thumbnail>,
+    timer: Timer,
+) -> Result<()> {
+    match timer {
+        Timer::Frame(window) => {
+            if let Some(thumbnail) = eves.get_mut(&window)
+                && thumbnail.pending_damage
+            {
+                thumbnail.frame()?;
+            }
+        }
+        Timer::Zoom(window) => {
+            if let Some(thumbnail) = eves.get_mut(&window)
+                && thumbnail.visible
+                && !thumbnail.input_state.dragging
+            {
+                let monitor = monitors::containing(
+                    &ctx.monitors,
+                    thumbnail.rect,
+                    ctx.config.output.as_deref(),
+                )
+                .map(|x| x.rect);
+                thumbnail.show_zoom(monitor, ctx.config.zoom_width, ctx.config.zoom_anchor)?;
+            }
+        }
+        Timer::Reap => ctx.hooks.reap(),
+    }
+    Ok(())
+}
+
+fn find_character<'e, 'a>(
+    eves: &'e mut HashMap<Window, Thumbnail<'a>>,
+    character_name: &str,
+) -> Result<&'e mut Thumbnail<'a>> {
+    eves.values_mut()
+        .find(|x| x.character_name == character_name)
+        .ok_or_else(|| anyhow::anyhow!("no client for character '{character_name}'"))
+}
+
+/// Re-reads the config file and applies it to the hotkeys, the layout and every thumbnail.
+fn reload_config(ctx: &mut Context, eves: &mut HashMap<Window, Thumbnail>) -> Result<()> {
+    let config = Config::load(ctx.config_path.as_deref())?;
+    info!("reloaded config={config:#?}");
+
+    let X11 { conn, screen, .. } = ctx.x11;
+    check_composite(conn, &config)?;
+    ctx.hotkeys.ungrab(conn, screen)?;
+    ctx.hotkeys = Hotkeys::grab(conn, screen, &config.hotkeys)?;
+    ctx.monitors = monitors::query(conn, screen, config.output.as_deref());
+    for thumbnail in eves.values_mut() {
+        thumbnail.reconfigure(thumbnail_config(
+            &config,
+            &ctx.saved,
+            &thumbnail.character_name,
+        ))?;
+    }
+    ctx.config = config;
+    apply_layout(&ctx.config, &ctx.monitors, eves)?;
+    Ok(())
+}
+
+fn status(eves: &HashMap<Window, Thumbnail>) -> Status {
+    let mut characters = eves
+        .values()
+        .map(|x| x.character_name.clone())
+        .filter(|x| !x.is_empty())
+        .collect::<Vec<_>>();
+    characters.sort();
+    let mut minimized = eves
+        .values()
+        .filter(|x| x.minimized && !x.character_name.is_empty())
+        .map(|x| x.character_name.clone())
+        .collect::<Vec<_>>();
+    minimized.sort();
+    Status {
+        count: eves.len(),
+        characters,
+        focused: eves
+            .values()
+            .find(|x| x.focused)
+            .map(|x| x.character_name.clone())
+            .filter(|x| !x.is_empty()),
+        minimized,
+    }
+}
+
+fn handle_request<'a>(
+    ctx: &mut Context<'a>,
+    eves: &mut HashMap<Window, Thumbnail<'a>>,
+    request: Request,
+) -> Result<Response> {
+    match request {
+        Request::List => {
+            let mut clients = eves
+                .values()
+                .map(|x| ClientInfo {
+                    window: x.src,
+                    character: x.character_name.clone(),
+                    focused: x.focused,
+                    minimized: x.minimized,
+                    visible: x.visible,
+                })
+                .collect::<Vec<_>>();
+            clients.sort_by(|a, b| (&a.character, a.window).cmp(&(&b.character, b.window)));
+            return Ok(Response {
+                clients: Some(clients),
+                ..Response::ok()
+            });
+        }
+        Request::Focus { character } => find_character(eves, &character)?.focus()?,
+        Request::Move { character, x, y } => {
+            let thumbnail = find_character(eves, &character)?;
+            thumbnail.reposition(x, y)?;
+            emit(
+                &mut ctx.events,
+                &mut ctx.hooks,
+                &ctx.config.hooks,
+                thumbnail.pid,
+                ClientEvent::Moved {
+                    window: thumbnail.src,
+                    character: character.clone(),
+                    x,
+                    y,
+                },
+            );
+            ctx.saved.set_position(&character, x, y);
+        }
+        Request::Resize {
+            character,
+            width,
+            height,
+        } => {
+            if width == 0 || height == 0 {
+                anyhow::bail!("thumbnail size must not be zero");
+            }
+            let thumbnail = find_character(eves, &character)?;
+            thumbnail.resize(width, height)?;
+            let Rect { x, y, .. } = thumbnail.rect;
+            let (width, height) = (thumbnail.config.width, thumbnail.config.height);
+            ctx.saved.set_geometry(&character, x, y, width, height);
+            apply_layout(&ctx.config, &ctx.monitors, eves)?;
+        }
+        Request::Show | Request::Hide => {
+            for thumbnail in eves.values_mut() {
+                thumbnail.visibility(matches!(request, Request::Show))?;
+            }
+            ctx.x11.conn.flush()?;
+        }
+        Request::Reload => reload_config(ctx, eves)?,
+        Request::Layout { mode } => {
+            ctx.config.layout.mode = mode;
+            apply_layout(&ctx.config, &ctx.monitors, eves)?;
+        }
+        // Answered by `ipc::Server` itself.
+        Request::Subscribe => (),
+    }
+    Ok(Response::ok())
+}
+
+#[derive(Debug, Default)]
+struct Args {
+    config: Option<PathBuf>,
+    /// Arguments following `ctl`, which talks to a running instance instead of starting one.
+    ctl: Option<Vec<String>>,
+}
+
+impl Args {
+    fn parse() -> Result<Self> {
+        let mut args = Self::default();
+        let mut iter = env::args().skip(1);
+        while let Some(arg) = iter.next() {
+            if arg == "--config" {
+                let Some(path) = iter.next() else {
+                    anyhow::bail!("'--config' requires a path");
+                };
+                args.config = Some(path.into());
+            } else if let Some(path) = arg.strip_prefix("--config=") {
+                args.config = Some(path.into());
+            } else if arg == "ctl" {
+                args.ctl = Some(iter.collect());
+                break;
+            } else {
+                anyhow::bail!("unknown argument '{arg}'");
+            }
+        }
+        Ok(args)
+    }
+}
+
 fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let args = Args::parse()?;
+    if let Some(ctl) = args.ctl {
+        return Ok(ctl::run(ctl)?);
+    }
+
     let subscriber = FmtSubscriber::builder()
         .with_max_level(TraceLevel::INFO)
         .finish();
 
     tracing::subscriber::set_global_default(subscriber)?;
 
-    let config = Config::new();
+    let config = Config::load(args.config.as_deref())?;
     info!("config={config:#?}");
 
     let (conn, screen_num) = x11rb::connect(None)?;
     let screen = &conn.setup().roots[screen_num];
+    conn.xfixes_query_version(5, 0)?.reply()?;
     conn.damage_query_version(1, 1)?;
+    check_composite(&conn, &config)?;
     conn.change_window_attributes(
         screen.root,
         &ChangeWindowAttributesAux::new().event_mask(
-            EventMask::SUBSTRUCTURE_NOTIFY
+            EventMask::STRUCTURE_NOTIFY
+                | EventMask::SUBSTRUCTURE_NOTIFY
                 | EventMask::BUTTON_PRESS
                 | EventMask::BUTTON_RELEASE
                 | EventMask::POINTER_MOTION,
         ),
     )?;
     info!("successfully connected to x11: screen={screen_num}");
+    let atoms = Atoms::new(&conn)?.reply()?;
+    let formats = PictFormats::query(&conn)?;
 
-    let mut eves = get_eves(&conn, screen, &config)?;
+    let _ = monitors::select_input(&conn, screen)
+        .inspect_err(|e| error!("failed to select RandR events: err={e:?}"));
+    let monitors = monitors::query(&conn, screen, config.output.as_deref());
+    let hotkeys = Hotkeys::grab(&conn, screen, &config.hotkeys)
+        .inspect_err(|e| error!("failed to grab hotkeys: err={e:?}"))
+        .unwrap_or_default();
+
+    let saved = SavedState::load();
+    let x11 = X11 {
+        conn: &conn,
+        atoms: &atoms,
+        formats: &formats,
+        screen,
+    };
+    let mut eves = get_eves(&x11, &config, &saved, &monitors)?;
+    apply_layout(&config, &monitors, &mut eves)?;
+    let mut ctx = Context {
+        x11,
+        config_path: args.config,
+        config,
+        saved,
+        monitors,
+        hotkeys,
+        events: Vec::new(),
+        hooks: Hooks::default(),
+        timers: Timers::default(),
+    };
+    let mut server = ipc::Server::bind()
+        .inspect_err(|e| error!("control socket is disabled: err={e:?}"))
+        .ok();
+    let mut status_file = StatusFile::new();
+    status_file.update(status(&eves));
     loop {
-        let event = conn.wait_for_event()?;
-        let _ = handle_event(&conn, screen, &config, &mut eves, event)
-            .inspect_err(|err| error!("ecountered error in 'handle_event': err={err:#?}"));
+        // Timers and requests go first: replies they wait for can pull events off the socket into
+        // the connection's queue, which has to be drained before blocking on the socket again.
+        for timer in ctx.timers.expire(Instant::now()) {
+            let _ = handle_timer(&mut ctx, &mut eves, timer)
+                .inspect_err(|err| error!("encountered error in 'handle_timer': err={err:#?}"));
+        }
+        if let Some(server) = &mut server {
+            server.service(|request| {
+                handle_request(&mut ctx, &mut eves, request).unwrap_or_else(|err| {
+                    error!("encountered error in 'handle_request': err={err:#?}");
+                    Response::error(format!("{err:#}"))
+                })
+            });
+        }
+        while let Some(event) = conn.poll_for_event()? {
+            let _ = handle_event(&mut ctx, &mut eves, event)
+                .inspect_err(|err| error!("ecountered error in 'handle_event': err={err:#?}"));
+        }
+        conn.flush()?;
+        ctx.hooks.reap();
+        if ctx.hooks.running() {
+            ctx.timers
+                .schedule(Instant::now() + HOOK_REAP_INTERVAL, Timer::Reap);
+        }
+        if !ctx.events.is_empty() {
+            status_file.update(status(&eves));
+        }
+        if let Some(server) = &mut server {
+            server.publish(ctx.events.drain(..));
+        } else {
+            ctx.events.clear();
+        }
+
+        let server_fds = server.as_ref().map(|x| x.fds()).unwrap_or_default();
+        event_loop::wait(
+            std::iter::once(conn.stream().as_fd()).chain(server_fds),
+            ctx.timers.next(),
+        )?;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn scaled_grows_by_a_pixel() {
+        assert_eq!(
+            Rect::new(10, 20, 30, 40).scaled(0.5, 0.5, 100, 100),
+            Some(Rect::new(4, 9, 17, 22))
+        );
+    }
+
+    #[test]
+    fn scaled_clips_to_size() {
+        assert_eq!(
+            Rect::new(0, 0, 10, 10).scaled(1.0, 1.0, 8, 8),
+            Some(Rect::new(0, 0, 8, 8))
+        );
+        assert_eq!(Rect::new(200, 200, 10, 10).scaled(1.0, 1.0, 100, 100), None);
+    }
+
+    #[test]
+    fn intersect() {
+        assert_eq!(
+            Rect::new(0, 0, 100, 100).intersect(&Rect::new(50, 60, 100, 100)),
+            Some(Rect::new(50, 60, 50, 40))
+        );
+        assert_eq!(
+            Rect::new(-10, -10, 20, 20).intersect(&Rect::new(-20, 5, 15, 3)),
+            Some(Rect::new(-10, 5, 5, 3))
+        );
+        // Touching edges don't overlap.
+        assert_eq!(
+            Rect::new(0, 0, 10, 10).intersect(&Rect::new(10, 0, 10, 10)),
+            None
+        );
     }
 }
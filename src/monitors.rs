@@ -0,0 +1,236 @@
+use anyhow::Result;
+use tracing::{error, info, warn};
+use x11rb::connection::Connection;
+use x11rb::protocol::randr::ConnectionExt as RandrExt;
+use x11rb::protocol::xproto::{ConnectionExt, Screen};
+use x11rb::rust_connection::RustConnection;
+
+use crate::Rect;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Monitor {
+    pub name: String,
+    pub primary: bool,
+    pub rect: Rect,
+}
+
+fn get_monitors(conn: &RustConnection, screen: &Screen) -> Result<Vec<Monitor>> {
+    let reply = conn.randr_get_monitors(screen.root, true)?.reply()?;
+    let mut monitors = Vec::with_capacity(reply.monitors.len());
+    for info in reply.monitors {
+        let name = conn.get_atom_name(info.name)?.reply()?.name;
+        monitors.push(Monitor {
+            name: String::from_utf8_lossy(&name).into_owned(),
+            primary: info.primary,
+            rect: Rect::new(info.x, info.y, info.width, info.height),
+        });
+    }
+    Ok(monitors)
+}
+
+/// Queries the active monitors through RandR, falling back to the whole screen if that fails.
+/// Warns if `output` is configured but not connected.
+pub fn query(conn: &RustConnection, screen: &Screen, output: Option<&str>) -> Vec<Monitor> {
+    let mut monitors = get_monitors(conn, screen)
+        .inspect_err(|e| {
+            error!("failed to query RandR monitors, using the whole screen: err={e:?}")
+        })
+        .unwrap_or_default();
+    if monitors.is_empty() {
+        monitors.push(Monitor {
+            name: String::new(),
+            primary: true,
+            rect: Rect::new(0, 0, screen.width_in_pixels, screen.height_in_pixels),
+        });
+    }
+    info!("monitors={monitors:?}");
+
+    if let Some(output) = output
+        && !monitors.iter().any(|x| x.name == output)
+    {
+        warn!("configured output '{output}' is not connected, using the primary monitor");
+    }
+    monitors
+}
+
+/// Selects RRScreenChangeNotify and the CRTC and output change notifies on the root window.
+/// Monitors added with `xrandr --setmonitor` only show up as a ConfigureNotify of the root window,
+/// which needs `STRUCTURE_NOTIFY` on it.
+pub fn select_input(conn: &RustConnection, screen: &Screen) -> Result<()> {
+    use x11rb::protocol::randr::NotifyMask;
+
+    conn.randr_query_version(1, 5)?.reply()?;
+    conn.randr_select_input(
+        screen.root,
+        NotifyMask::SCREEN_CHANGE | NotifyMask::CRTC_CHANGE | NotifyMask::OUTPUT_CHANGE,
+    )?;
+    conn.flush()?;
+    Ok(())
+}
+
+/// The configured `output` if connected, otherwise the primary monitor or the first one.
+pub fn target<'m>(monitors: &'m [Monitor], output: Option<&str>) -> Option<&'m Monitor> {
+    output
+        .and_then(|output| monitors.iter().find(|x| x.name == output))
+        .or_else(|| monitors.iter().find(|x| x.primary))
+        .or_else(|| monitors.first())
+}
+
+/// The monitor the center of `rect` is on, falling back to the `target` one.
+pub fn containing<'m>(
+    monitors: &'m [Monitor],
+    rect: Rect,
+    output: Option<&str>,
+) -> Option<&'m Monitor> {
+    let (cx, cy) = rect.center();
+    monitors
+        .iter()
+        .find(|x| x.rect.contains(cx, cy))
+        .or_else(|| target(monitors, output))
+}
+
+/// Moves a `width` x `height` rectangle at `x`, `y` as little as possible to lie within `monitor`.
+/// Rectangles larger than the monitor are aligned with its top left corner.
+pub fn clamp(x: i32, y: i32, width: u16, height: u16, monitor: Rect) -> (i16, i16) {
+    let x = x
+        .min(monitor.x as i32 + monitor.width as i32 - width as i32)
+        .max(monitor.x as i32);
+    let y = y
+        .min(monitor.y as i32 + monitor.height as i32 - height as i32)
+        .max(monitor.y as i32);
+    (x as i16, y as i16)
+}
+
+/// Returns where a thumbnail at `rect` has to move to stay visible after the monitor layout changed
+/// from `old` to `new`, or `None` if its center is still on a monitor. The offset from the origin
+/// of the monitor it was on is kept where possible.
+pub fn relocate(
+    rect: Rect,
+    old: &[Monitor],
+    new: &[Monitor],
+    output: Option<&str>,
+) -> Option<(i16, i16)> {
+    let (cx, cy) = rect.center();
+    if new.iter().any(|x| x.rect.contains(cx, cy)) {
+        return None;
+    }
+    let target = target(new, output)?.rect;
+    let (dx, dy) = old
+        .iter()
+        .find(|x| x.rect.contains(cx, cy))
+        .map_or((0, 0), |x| {
+            (
+                rect.x as i32 - x.rect.x as i32,
+                rect.y as i32 - x.rect.y as i32,
+            )
+        });
+    Some(clamp(
+        target.x as i32 + dx,
+        target.y as i32 + dy,
+        rect.width,
+        rect.height,
+        target,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn relocate_keeps_offset_from_removed_monitor() {
+        let left = Monitor {
+            name: "DP-1".into(),
+            primary: true,
+            rect: Rect::new(0, 0, 1920, 1080),
+        };
+        let right = Monitor {
+            name: "DP-2".into(),
+            primary: false,
+            rect: Rect::new(1920, 0, 1920, 1080),
+        };
+        let old = [left.clone(), right];
+        let new = [left];
+        assert_eq!(
+            relocate(Rect::new(2020, 50, 200, 100), &old, &new, None),
+            Some((100, 50))
+        );
+        // Still on a connected monitor.
+        assert_eq!(
+            relocate(Rect::new(100, 50, 200, 100), &old, &new, None),
+            None
+        );
+    }
+
+    #[test]
+    fn relocate_clamps_into_smaller_monitor() {
+        let small = Monitor {
+            name: "DP-1".into(),
+            primary: false,
+            rect: Rect::new(0, 0, 1280, 720),
+        };
+        let large = Monitor {
+            name: "DP-2".into(),
+            primary: false,
+            rect: Rect::new(1280, 0, 2560, 1440),
+        };
+        let old = [small.clone(), large];
+        let new = [small];
+        assert_eq!(
+            relocate(Rect::new(3500, 1300, 200, 100), &old, &new, None),
+            Some((1080, 620))
+        );
+    }
+
+    #[test]
+    fn relocate_prefers_configured_output() {
+        let old = [Monitor {
+            name: "DP-3".into(),
+            primary: false,
+            rect: Rect::new(3840, 0, 1920, 1080),
+        }];
+        let new = [
+            Monitor {
+                name: "DP-1".into(),
+                primary: true,
+                rect: Rect::new(0, 0, 1920, 1080),
+            },
+            Monitor {
+                name: "DP-2".into(),
+                primary: false,
+                rect: Rect::new(1920, 0, 1920, 1080),
+            },
+        ];
+        let rect = Rect::new(3840, 0, 200, 100);
+        assert_eq!(relocate(rect, &old, &new, Some("DP-2")), Some((1920, 0)));
+        assert_eq!(relocate(rect, &old, &new, Some("HDMI-1")), Some((0, 0)));
+    }
+
+    #[test]
+    fn containing_falls_back_to_target() {
+        let monitors = [
+            Monitor {
+                name: "DP-1".into(),
+                primary: false,
+                rect: Rect::new(0, 0, 1920, 1080),
+            },
+            Monitor {
+                name: "DP-2".into(),
+                primary: true,
+                rect: Rect::new(1920, 0, 1920, 1080),
+            },
+        ];
+        let name = |rect| containing(&monitors, rect, None).map(|x| x.name.as_str());
+        assert_eq!(name(Rect::new(100, 100, 200, 100)), Some("DP-1"));
+        assert_eq!(name(Rect::new(-500, 100, 200, 100)), Some("DP-2"));
+    }
+
+    #[test]
+    fn clamp_moves_onto_monitor() {
+        let monitor = Rect::new(1920, 0, 1920, 1080);
+        assert_eq!(clamp(3800, -20, 200, 100, monitor), (3640, 0));
+        assert_eq!(clamp(2000, 500, 200, 100, monitor), (2000, 500));
+        // Larger than the monitor.
+        assert_eq!(clamp(2000, 500, 2000, 100, monitor), (1920, 500));
+    }
+}
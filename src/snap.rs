@@ -0,0 +1,116 @@
+use crate::Rect;
+
+/// Returns the candidate closest to `value` if it lies within `threshold`.
+fn closest(value: i32, candidates: impl Iterator<Item = i32>, threshold: i32) -> Option<i32> {
+    candidates
+        .filter(|candidate| (candidate - value).abs() <= threshold)
+        .min_by_key(|candidate| (candidate - value).abs())
+}
+
+/// Edge, center and adjacency positions a span of `size` can snap to along one axis of `other`.
+fn thumbnail_candidates(start: i32, length: i32, size: i32, gap: i32) -> [i32; 5] {
+    [
+        start,
+        start + length - size,
+        start + (length - size) / 2,
+        start + length + gap,
+        start - gap - size,
+    ]
+}
+
+/// Snaps `rect` to the edges and centers of `targets` and to the edges of `bounds`.
+/// Each axis is snapped independently, the nearest candidate within `threshold` wins.
+pub fn snap_position(
+    rect: Rect,
+    targets: &[Rect],
+    bounds: &[Rect],
+    threshold: u16,
+    gap: u16,
+) -> (i16, i16) {
+    let threshold = threshold as i32;
+    let gap = gap as i32;
+    let (width, height) = (rect.width as i32, rect.height as i32);
+
+    let xs = targets
+        .iter()
+        .flat_map(|t| thumbnail_candidates(t.x as i32, t.width as i32, width, gap))
+        .chain(
+            bounds
+                .iter()
+                .flat_map(|b| [b.x as i32, b.x as i32 + b.width as i32 - width]),
+        );
+    let ys = targets
+        .iter()
+        .flat_map(|t| thumbnail_candidates(t.y as i32, t.height as i32, height, gap))
+        .chain(
+            bounds
+                .iter()
+                .flat_map(|b| [b.y as i32, b.y as i32 + b.height as i32 - height]),
+        );
+
+    let x = closest(rect.x as i32, xs, threshold).map_or(rect.x, |x| x as i16);
+    let y = closest(rect.y as i32, ys, threshold).map_or(rect.y, |y| y as i16);
+    (x, y)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn snaps_to_edges_within_threshold() {
+        let target = Rect::new(100, 100, 200, 100);
+        // Left edges 4 pixels apart, bottom edges 3 pixels apart.
+        assert_eq!(
+            snap_position(Rect::new(104, 147, 50, 50), &[target], &[], 5, 0),
+            (100, 150)
+        );
+        // One pixel beyond the threshold stays put.
+        assert_eq!(
+            snap_position(Rect::new(106, 144, 50, 50), &[target], &[], 5, 0),
+            (106, 144)
+        );
+    }
+
+    #[test]
+    fn snaps_to_center() {
+        let target = Rect::new(0, 0, 200, 100);
+        assert_eq!(
+            snap_position(Rect::new(73, 300, 50, 50), &[target], &[], 5, 0),
+            (75, 300)
+        );
+    }
+
+    #[test]
+    fn snaps_next_to_target_with_gap() {
+        let target = Rect::new(100, 100, 200, 100);
+        // Right of the target.
+        assert_eq!(
+            snap_position(Rect::new(305, 100, 50, 50), &[target], &[], 8, 10),
+            (310, 100)
+        );
+        // Above the target.
+        assert_eq!(
+            snap_position(Rect::new(100, 38, 50, 50), &[target], &[], 8, 10),
+            (100, 40)
+        );
+    }
+
+    #[test]
+    fn snaps_to_bounds() {
+        let bounds = Rect::new(0, 0, 1920, 1080);
+        assert_eq!(
+            snap_position(Rect::new(1815, 3, 100, 50), &[], &[bounds], 10, 0),
+            (1820, 0)
+        );
+    }
+
+    #[test]
+    fn nearest_candidate_wins() {
+        let targets = [Rect::new(100, 0, 50, 50), Rect::new(106, 500, 50, 50)];
+        assert_eq!(
+            snap_position(Rect::new(104, 250, 50, 50), &targets, &[], 10, 0),
+            (106, 250)
+        );
+    }
+}
@@ -0,0 +1,134 @@
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::PathBuf;
+use tracing::{error, info, warn};
+
+use crate::config::xdg_dir;
+
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+pub struct SavedThumbnail {
+    pub x: i16,
+    pub y: i16,
+    /// Size the thumbnail was resized to, overriding the configured one.
+    #[serde(default)]
+    pub width: Option<u16>,
+    #[serde(default)]
+    pub height: Option<u16>,
+}
+
+/// Thumbnail state that survives restarts, stored in `$XDG_STATE_HOME/eve-l-preview/state.toml`.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct SavedState {
+    #[serde(skip)]
+    path: Option<PathBuf>,
+    #[serde(default)]
+    characters: HashMap<String, SavedThumbnail>,
+    /// Positions of region windows, by character and region name.
+    #[serde(default)]
+    regions: HashMap<String, HashMap<String, SavedThumbnail>>,
+}
+
+impl SavedState {
+    pub fn load() -> Self {
+        let Some(path) =
+            xdg_dir("XDG_STATE_HOME", ".local/state").map(|x| x.join("eve-l-preview/state.toml"))
+        else {
+            warn!("neither XDG_STATE_HOME nor HOME is set, thumbnail positions will not be saved");
+            return Self::default();
+        };
+
+        let mut state = match std::fs::read_to_string(&path) {
+            Ok(text) => toml::from_str::<Self>(&text)
+                .inspect_err(|e| error!("failed to parse '{}': err={e}", path.display()))
+                .unwrap_or_default(),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Self::default(),
+            Err(e) => {
+                error!("failed to read '{}': err={e}", path.display());
+                Self::default()
+            }
+        };
+        info!(
+            "loaded saved state: path={} characters={}",
+            path.display(),
+            state.characters.len()
+        );
+        state.path = Some(path);
+        state
+    }
+
+    pub fn get(&self, character_name: &str) -> Option<SavedThumbnail> {
+        self.characters.get(character_name).copied()
+    }
+
+    pub fn get_region(&self, character_name: &str, region: &str) -> Option<SavedThumbnail> {
+        self.regions.get(character_name)?.get(region).copied()
+    }
+
+    /// Records the position of the window of `region` of `character_name`.
+    pub fn set_region_position(&mut self, character_name: &str, region: &str, x: i16, y: i16) {
+        if character_name.is_empty() {
+            return;
+        }
+        let entry = self
+            .regions
+            .entry(character_name.to_string())
+            .or_default()
+            .entry(region.to_string())
+            .or_default();
+        entry.x = x;
+        entry.y = y;
+        let _ = self
+            .save()
+            .inspect_err(|e| error!("failed to save thumbnail state: err={e:?}"));
+    }
+
+    /// Records the position of `character_name` and writes the state file.
+    /// Clients sitting at character select have no name and are not saved.
+    pub fn set_position(&mut self, character_name: &str, x: i16, y: i16) {
+        self.update(character_name, |entry| {
+            entry.x = x;
+            entry.y = y;
+        });
+    }
+
+    /// Records the position and size of `character_name` after it was resized.
+    pub fn set_geometry(&mut self, character_name: &str, x: i16, y: i16, width: u16, height: u16) {
+        self.update(character_name, |entry| {
+            entry.x = x;
+            entry.y = y;
+            entry.width = Some(width);
+            entry.height = Some(height);
+        });
+    }
+
+    fn update(&mut self, character_name: &str, f: impl FnOnce(&mut SavedThumbnail)) {
+        if character_name.is_empty() {
+            return;
+        }
+        f(self
+            .characters
+            .entry(character_name.to_string())
+            .or_default());
+        let _ = self
+            .save()
+            .inspect_err(|e| error!("failed to save thumbnail state: err={e:?}"));
+    }
+
+    fn save(&self) -> Result<()> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)
+                .with_context(|| format!("failed to create '{}'", dir.display()))?;
+        }
+        let text = toml::to_string(self)?;
+        let tmp = path.with_extension("toml.tmp");
+        std::fs::write(&tmp, text)
+            .with_context(|| format!("failed to write '{}'", tmp.display()))?;
+        std::fs::rename(&tmp, path)
+            .with_context(|| format!("failed to replace '{}'", path.display()))?;
+        Ok(())
+    }
+}
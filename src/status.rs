@@ -0,0 +1,70 @@
+use anyhow::{Context, Result};
+use serde::Serialize;
+use std::path::PathBuf;
+use tracing::{error, info, warn};
+
+use crate::runtime_dir;
+
+/// Snapshot of the tracked clients written for status bars.
+#[derive(Debug, Default, PartialEq, Eq, Serialize)]
+pub struct Status {
+    /// Number of EVE clients, including those sitting at character select.
+    pub count: usize,
+    /// Logged in characters, sorted by name.
+    pub characters: Vec<String>,
+    /// The focused character, `None` if no client or one at character select has focus.
+    pub focused: Option<String>,
+    /// Minimized logged in characters, sorted by name.
+    pub minimized: Vec<String>,
+}
+
+/// `$XDG_RUNTIME_DIR/eve-l-preview.json`, atomically replaced whenever the status changes.
+#[derive(Debug)]
+pub struct StatusFile {
+    path: Option<PathBuf>,
+    last: Option<Status>,
+}
+
+impl StatusFile {
+    pub fn new() -> Self {
+        let path = runtime_dir().map(|x| x.join("eve-l-preview.json"));
+        match &path {
+            Some(path) => info!("writing status to path={}", path.display()),
+            None => warn!("XDG_RUNTIME_DIR is not set, the status file is disabled"),
+        }
+        Self { path, last: None }
+    }
+
+    /// Writes `status` unless it is the same as the last one written.
+    pub fn update(&mut self, status: Status) {
+        if self.last.as_ref() == Some(&status) {
+            return;
+        }
+        let _ = self
+            .write(&status)
+            .inspect_err(|e| error!("failed to write status file: err={e:?}"));
+        self.last = Some(status);
+    }
+
+    fn write(&self, status: &Status) -> Result<()> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        let mut text = serde_json::to_vec(status)?;
+        text.push(b'\n');
+        let tmp = path.with_extension("json.tmp");
+        std::fs::write(&tmp, text)
+            .with_context(|| format!("failed to write '{}'", tmp.display()))?;
+        std::fs::rename(&tmp, path)
+            .with_context(|| format!("failed to replace '{}'", path.display()))?;
+        Ok(())
+    }
+}
+
+impl Drop for StatusFile {
+    fn drop(&mut self) {
+        if let Some(path) = &self.path {
+            let _ = std::fs::remove_file(path);
+        }
+    }
+}
@@ -0,0 +1,120 @@
+use anyhow::Result;
+use x11rb::connection::Connection;
+use x11rb::protocol::render::{ConnectionExt as RenderExt, CreatePictureAux, Picture};
+use x11rb::protocol::shape::{ConnectionExt as ShapeExt, SK, SO};
+use x11rb::protocol::xproto::*;
+
+use crate::layout::Anchor;
+use crate::{Rect, Thumbnail, create_preview_window, monitors};
+
+/// Enlarged preview of the source, shown while the pointer rests on the thumbnail.
+#[derive(Debug)]
+pub struct Zoom {
+    window: Window,
+    picture: Picture,
+    width: u16,
+    height: u16,
+}
+
+impl Thumbnail<'_> {
+    /// Maps the zoom preview `width` pixels wide, sharing the `anchor` corner with the thumbnail
+    /// but moved onto `monitor` where it would stick out of it.
+    pub fn show_zoom(&mut self, monitor: Option<Rect>, width: u16, anchor: Anchor) -> Result<()> {
+        if self.zoom.is_some() {
+            return Ok(());
+        }
+        let height = (width as u32 * self.src_size.1 as u32 / self.src_size.0.max(1) as u32)
+            .clamp(1, u16::MAX as u32) as u16;
+        let (left, top) = (self.rect.x as i32, self.rect.y as i32);
+        let right = left + self.rect.width as i32 - width as i32;
+        let bottom = top + self.rect.height as i32 - height as i32;
+        let (x, y) = match anchor {
+            Anchor::TopLeft => (left, top),
+            Anchor::TopRight => (right, top),
+            Anchor::BottomLeft => (left, bottom),
+            Anchor::BottomRight => (right, bottom),
+        };
+        let (x, y) = monitor.map_or((x as i16, y as i16), |monitor| {
+            monitors::clamp(x, y, width, height, monitor)
+        });
+
+        let window = create_preview_window(
+            self.conn,
+            self.atoms,
+            self.root,
+            Rect::new(x, y, width, height),
+            u32::MAX,
+            EventMask::NO_EVENT,
+        )?;
+        // An empty input shape lets the pointer through, so that the thumbnail underneath keeps
+        // receiving the motion and the leave event that hides the preview again.
+        self.conn.shape_rectangles(
+            SO::SET,
+            SK::INPUT,
+            ClipOrdering::UNSORTED,
+            window,
+            0,
+            0,
+            &[],
+        )?;
+        self.conn.map_window(window)?;
+
+        let picture = self.conn.generate_id()?;
+        self.conn.render_create_picture(
+            picture,
+            window,
+            self.dst_format,
+            &CreatePictureAux::new(),
+        )?;
+        self.zoom = Some(Zoom {
+            window,
+            picture,
+            width,
+            height,
+        });
+        self.update_zoom()?;
+        Ok(())
+    }
+
+    pub fn hide_zoom(&mut self) -> Result<()> {
+        if let Some(zoom) = self.zoom.take() {
+            self.conn.render_free_picture(zoom.picture)?;
+            self.conn.destroy_window(zoom.window)?;
+        }
+        Ok(())
+    }
+
+    /// Repaints the whole zoom preview, if it is shown.
+    pub fn update_zoom(&self) -> Result<()> {
+        let Some(zoom) = &self.zoom else {
+            return Ok(());
+        };
+        // The preview borrows `src_picture` with its own scale, the thumbnail's is restored below.
+        self.set_scaling(
+            self.src_picture,
+            (self.src_size.0.max(1), self.src_size.1.max(1)),
+            (zoom.width, zoom.height),
+        )?;
+        let op = self.background(
+            zoom.picture,
+            Rect::new(0, 0, zoom.width, zoom.height),
+            false,
+        )?;
+        self.conn.render_composite(
+            op,
+            self.src_picture,
+            0u32,
+            zoom.picture,
+            0,
+            0,
+            0,
+            0,
+            0,
+            0,
+            zoom.width,
+            zoom.height,
+        )?;
+        self.scale_source()?;
+        Ok(())
+    }
+}